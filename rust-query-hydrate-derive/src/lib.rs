@@ -6,45 +6,125 @@ use parking_lot::Mutex;
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span};
 use proc_macro_crate::{crate_name, FoundCrate};
-use quote::ToTokens;
 use syn::{
-    parse::Error, punctuated::Punctuated, spanned::Spanned, token::Paren,
-    AngleBracketedGenericArguments, Data, DeriveInput, Type, TypeTuple,
+    parse::Error,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    token::{Comma, Paren},
+    AngleBracketedGenericArguments, Attribute, Data, DeriveInput, GenericArgument, Type, TypeTuple,
 };
 
+const EXPECTED_2_ARGS: &str = "expected 2 generic arguments: #[result(<R, E>)]";
+
+/// Hydration keys used so far, shared by queries & mutations so every key is unique
 static KEYS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
 #[proc_macro_derive(HydratableQuery, attributes(result, param))]
 pub fn hydratable_query_derive(input: TokenStream) -> TokenStream {
+    derive(input, Kind::Query)
+}
+
+#[proc_macro_derive(HydratableMutation, attributes(result, param))]
+pub fn hydratable_mutation_derive(input: TokenStream) -> TokenStream {
+    derive(input, Kind::Mutation)
+}
+
+/// What is being derived
+#[derive(Clone, Copy)]
+enum Kind {
+    Query,
+    Mutation,
+}
+
+impl Kind {
+    fn trait_name(self) -> &'static str {
+        match self {
+            Self::Query => "HydratableQuery",
+            Self::Mutation => "HydratableMutation",
+        }
+    }
+
+    fn builder_name(self) -> &'static str {
+        match self {
+            Self::Query => "HydratableQueryBuilder",
+            Self::Mutation => "HydratableMutationBuilder",
+        }
+    }
+}
+
+/// Associated types parsed from the attributes
+struct Types {
+    param: Type,
+    result: GenericArgument,
+    err: GenericArgument,
+}
+
+fn derive(input: TokenStream, kind: Kind) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(input, kind)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput, kind: Kind) -> Result<proc_macro2::TokenStream, Error> {
     let DeriveInput {
         attrs, ident, data, ..
-    } = syn::parse_macro_input!(input as DeriveInput);
-    let ident_name = ident.to_string();
+    } = input;
+    let ident_name = claim_key(&ident)?;
+    check_struct(&data, kind)?;
+    let Types { param, result, err } = parse_types(attrs)?;
 
+    let crate_ = match crate_name("sycamore-query")
+        .expect("sycamore-query should be present in Cargo.toml")
+    {
+        FoundCrate::Itself => "crate".to_owned(),
+        FoundCrate::Name(name) => name,
+    };
+    let crate_ = Ident::new(&crate_, Span::call_site());
+    let trait_ = Ident::new(kind.trait_name(), Span::call_site());
+    let builder = Ident::new(kind.builder_name(), Span::call_site());
+
+    Ok(quote::quote! {
+        // SAFETY: Trait intended to be implemented here
+        unsafe impl #trait_ for #ident {
+            type Param = #param;
+            type Result = #result;
+            type Error = #err;
+
+            fn builder() -> #crate_::hydrate::#builder<Self::Param, Self::Result, Self::Error> {
+                unsafe { #crate_::hydrate::#builder::new(#ident_name.to_owned()) }
+            }
+        }
+    })
+}
+
+/// Reserves the hydration key for `ident`, erroring if it's already been used
+fn claim_key(ident: &Ident) -> Result<String, Error> {
+    let ident_name = ident.to_string();
     if !KEYS.lock().insert(ident_name.clone()) {
-        return Error::new_spanned(ident, "duplicate hydratable key")
-            .into_compile_error()
-            .into();
+        return Err(Error::new_spanned(ident, "duplicate hydratable key"));
     }
+    Ok(ident_name)
+}
 
-    let wrong_type = match data {
-        Data::Struct(_) => None,
-        Data::Enum(e) => Some((e.enum_token.span, "enum")),
-        Data::Union(u) => Some((u.union_token.span, "union")),
+fn check_struct(data: &Data, kind: Kind) -> Result<(), Error> {
+    let (span, wrong_type) = match *data {
+        Data::Struct(_) => return Ok(()),
+        Data::Enum(ref e) => (e.enum_token.span, "enum"),
+        Data::Union(ref u) => (u.union_token.span, "union"),
     };
 
-    if let Some((span, wrong_type)) = wrong_type {
-        return Error::new(
-            span,
-            format!(
-                "expected struct, HydratableQuery cannot be derived for {}",
-                wrong_type
-            ),
-        )
-        .into_compile_error()
-        .into();
-    }
+    Err(Error::new(
+        span,
+        format!(
+            "expected struct, {} cannot be derived for {}",
+            kind.trait_name(),
+            wrong_type
+        ),
+    ))
+}
 
+fn parse_types(attrs: Vec<Attribute>) -> Result<Types, Error> {
     let mut result: Option<AngleBracketedGenericArguments> = None;
     let mut param = None;
 
@@ -52,74 +132,53 @@ pub fn hydratable_query_derive(input: TokenStream) -> TokenStream {
         if k.is_ident("result") {
             let result_attr = attr.next().unwrap();
             if let Some(dupe) = attr.next() {
-                return Error::new_spanned(dupe, "duplicate attribute")
-                    .into_compile_error()
-                    .into();
+                return Err(Error::new_spanned(dupe, "duplicate attribute"));
             }
-            result = Some(match result_attr.parse_args() {
-                Ok(r) => r,
-                Err(e) => {
-                    let mut e1 = Error::new(
-                        e.span(),
-                        "result attribute should be formatted like #[result(<R, E>)]",
-                    );
-                    e1.combine(e);
-                    return e1.into_compile_error().into();
-                }
-            });
+            result = Some(result_attr.parse_args().map_err(|e| {
+                let mut e1 = Error::new(
+                    e.span(),
+                    "result attribute should be formatted like #[result(<R, E>)]",
+                );
+                e1.combine(e);
+                e1
+            })?);
         } else if k.is_ident("param") {
             let param_attr = attr.next().unwrap();
             if let Some(dupe) = attr.next() {
-                return Error::new_spanned(dupe, "duplicate attribute")
-                    .into_compile_error()
-                    .into();
+                return Err(Error::new_spanned(dupe, "duplicate attribute"));
             }
-            param = Some(match param_attr.parse_args() {
-                Ok(p) => p,
-                Err(e) => {
-                    let mut e1 = Error::new(
-                        e.span(),
-                        "param attribute should be fomatted like #[param(P)]",
-                    );
-                    e1.combine(e);
-                    return e1.into_compile_error().into();
-                }
-            });
+            param = Some(param_attr.parse_args().map_err(|e| {
+                let mut e1 = Error::new(
+                    e.span(),
+                    "param attribute should be fomatted like #[param(P)]",
+                );
+                e1.combine(e);
+                e1
+            })?);
         }
     }
 
-    let flag = result.is_some();
-    let result = match result {
+    let (result, err) = match result {
         Some(r) if r.args.len() == 2 => {
             let mut iter = r.args.into_iter();
-            Ok((iter.next().unwrap(), iter.next().unwrap()))
+            (iter.next().unwrap(), iter.next().unwrap())
         }
-        Some(r) if r.args.is_empty() => Err(r.lt_token.span()),
-        Some(r) if r.args.len() == 1 => Err(r.gt_token.span()),
-        Some(r) => Err(r
-            .args
-            .iter()
-            .nth(2)
-            .unwrap()
-            .to_token_stream()
-            .into_iter()
-            .next()
-            .unwrap()
-            .span()),
-        None => Err(Span::call_site()),
-    };
-    let (result, err) = match result {
-        Ok((r, e)) => (r, e),
-        Err(e) => {
-            return Error::new(
-                e,
-                match flag {
-                    true => "expected 2 generic arguments: #[result(<R, E>)]",
-                    false => "expected #[result(<R, E>)] attribute",
-                },
-            )
-            .into_compile_error()
-            .into()
+        Some(r) if r.args.is_empty() => {
+            return Err(Error::new(r.lt_token.span(), EXPECTED_2_ARGS));
+        }
+        Some(r) if r.args.len() == 1 => {
+            return Err(Error::new(r.gt_token.span(), EXPECTED_2_ARGS));
+        }
+        // Point at every extra argument as a whole, each can be any type including nested generics
+        Some(r) => {
+            let extra: Punctuated<_, Comma> = r.args.into_iter().skip(2).collect();
+            return Err(Error::new_spanned(extra, EXPECTED_2_ARGS));
+        }
+        None => {
+            return Err(Error::new(
+                Span::call_site(),
+                "expected #[result(<R, E>)] attribute",
+            ))
         }
     };
     let param = match param {
@@ -130,25 +189,82 @@ pub fn hydratable_query_derive(input: TokenStream) -> TokenStream {
         }),
     };
 
-    let crate_ = match crate_name("sycamore-query")
-        .expect("sycamore-query should be present in Cargo.toml")
-    {
-        FoundCrate::Itself => "crate".to_owned(),
-        FoundCrate::Name(name) => name,
-    };
-    let crate_ = Ident::new(&crate_, Span::call_site());
+    Ok(Types { param, result, err })
+}
 
-    quote::quote! {
-        // SAFETY: Trait intended to be implemented here
-        unsafe impl HydratableQuery for #ident {
-            type Param = #param;
-            type Result = #result;
-            type Error = #err;
+#[cfg(test)]
+mod tests {
+    use quote::ToTokens;
+    use syn::DeriveInput;
 
-            fn builder() -> #crate_::hydrate::HydratableQueryBuilder<Self::Param, Self::Result, Self::Error> {
-                unsafe { #crate_::hydrate::HydratableQueryBuilder::new(#ident_name.to_owned()) }
-            }
-        }
+    use super::{check_struct, claim_key, parse_types, Kind, Types, EXPECTED_2_ARGS};
+
+    #[test]
+    fn mutation_types() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[param(String)]
+            #[result(<Vec<u8>, MyError>)]
+            struct AddTodo;
+        };
+        check_struct(&input.data, Kind::Mutation).unwrap();
+        let Types { param, result, err } = parse_types(input.attrs).unwrap();
+
+        assert_eq!(param.to_token_stream().to_string(), "String");
+        assert_eq!(result.to_token_stream().to_string(), "Vec < u8 >");
+        assert_eq!(err.to_token_stream().to_string(), "MyError");
+    }
+
+    #[test]
+    fn nested_generic_result() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[result(<Vec<Foo<u8>>, MyError<Bar>>)]
+            struct NestedGenerics;
+        };
+        let Types { result, err, .. } = parse_types(input.attrs).unwrap();
+
+        assert_eq!(result.to_token_stream().to_string(), "Vec < Foo < u8 > >");
+        assert_eq!(err.to_token_stream().to_string(), "MyError < Bar >");
+    }
+
+    #[test]
+    fn wrong_result_arg_count() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[result(<Vec<Foo>, MyError<Bar>, HashMap<u8, u8>>)]
+            struct TooManyArgs;
+        };
+        let Err(err) = parse_types(input.attrs) else {
+            panic!("three generic arguments should error");
+        };
+
+        assert_eq!(err.to_string(), EXPECTED_2_ARGS);
+
+        let input: DeriveInput = syn::parse_quote! {
+            #[result(<Vec<Foo>>)]
+            struct TooFewArgs;
+        };
+        let Err(err) = parse_types(input.attrs) else {
+            panic!("one generic argument should error");
+        };
+        assert_eq!(err.to_string(), EXPECTED_2_ARGS);
+    }
+
+    #[test]
+    fn mutation_not_struct() {
+        let input: DeriveInput = syn::parse_quote! {
+            enum AddTodo {}
+        };
+        let err = check_struct(&input.data, Kind::Mutation).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected struct, HydratableMutation cannot be derived for enum"
+        );
+    }
+
+    #[test]
+    fn duplicate_key() {
+        let ident = syn::parse_quote!(DuplicateKey);
+        assert_eq!(claim_key(&ident).unwrap(), "DuplicateKey");
+        let err = claim_key(&ident).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate hydratable key");
     }
-    .into()
 }
@@ -12,9 +12,27 @@ use wasm_bindgen::{prelude::*, JsCast};
 
 thread_local! {
     static ONLINE_HANDLER: RefCell<Weak<OnlineHandler>> = RefCell::new(Weak::new());
+    #[cfg(test)]
+    static ONLINE_OVERRIDE: Cell<Option<bool>> = const { Cell::new(None) };
+    #[cfg(test)]
+    static ONLINE_CHECKS: Cell<usize> = const { Cell::new(0) };
 }
 
-fn get_handler() -> Arc<OnlineHandler> {
+/// Override the connection status reported by [`is_online`] on this thread, `None` to use the browser's
+#[cfg(test)]
+pub(crate) fn set_online_override(online: Option<bool>) {
+    ONLINE_OVERRIDE.with(|o| o.set(online));
+}
+
+/// Counts the check, and gets the status set by [`set_online_override`], if any
+#[cfg(test)]
+fn checked_override() -> Option<bool> {
+    ONLINE_CHECKS.with(|c| c.set(c.get() + 1));
+    ONLINE_OVERRIDE.with(Cell::get)
+}
+
+/// Gets the handler for this thread, creating it if needed
+pub(crate) fn handler() -> Arc<OnlineHandler> {
     ONLINE_HANDLER.with(|handler| {
         let mut handler = handler.borrow_mut();
         handler.upgrade().unwrap_or_else(|| {
@@ -38,6 +56,11 @@ extern "C" {
 }
 
 pub(crate) fn is_online() -> bool {
+    #[cfg(test)]
+    if let Some(online) = checked_override() {
+        return online;
+    }
+
     let window: Window = js_sys::global()
         .dyn_into()
         .expect("should be able to get Window");
@@ -48,9 +71,22 @@ pub(crate) struct OnlineHandler {
     online: Cell<bool>,
     notify: Notify,
     event: OnceCell<JsEvent>,
+    offline_event: OnceCell<JsEvent>,
 }
 
 impl OnlineHandler {
+    /// As [`is_online`], but kept up to date by the browser's `online` & `offline` events rather than asking the browser each time
+    // Can't be const when testing, as the override is checked first
+    #[allow(clippy::missing_const_for_fn)]
+    pub(crate) fn online(&self) -> bool {
+        #[cfg(test)]
+        if let Some(online) = checked_override() {
+            return online;
+        }
+
+        self.online.get()
+    }
+
     fn check_online(&self) {
         if is_online() {
             self.set_online();
@@ -65,7 +101,7 @@ impl OnlineHandler {
     }
 
     pub(crate) async fn wait() {
-        let this = get_handler();
+        let this = handler();
         while !this.online.get() {
             let notify = this.notify.notified();
             tokio::pin!(notify);
@@ -77,9 +113,10 @@ impl OnlineHandler {
 
     fn new() -> Arc<Self> {
         let this = Arc::new(Self {
-            online: Cell::new(true),
+            online: Cell::new(is_online()),
             notify: Notify::new(),
             event: OnceCell::new(),
+            offline_event: OnceCell::new(),
         });
 
         this.event
@@ -90,6 +127,199 @@ impl OnlineHandler {
                 }
             }))
             .expect("should not fail to set the JsEvent of a newly created Self");
+        this.offline_event
+            .set(JsEvent::new("offline", {
+                let this = Arc::downgrade(&this);
+                move |_| {
+                    if let Some(this) = this.upgrade() {
+                        this.online.set(false);
+                    }
+                }
+            }))
+            .expect("should not fail to set the JsEvent of a newly created Self");
         this
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{handler, set_online_override, ONLINE_CHECKS};
+    use crate::{
+        client::QueryClient,
+        config::NetworkMode,
+        mutation::{Mutation, MutationCallbacks},
+        query::{Query, QueryOpts},
+        sleep::sleep,
+        status::{
+            CancelReason, FetchResult, FetchResultWaited, MutateError, PendingStatus, QueryData,
+        },
+    };
+
+    fn checks() -> usize {
+        ONLINE_CHECKS.with(Cell::get)
+    }
+
+    #[wasm_bindgen_test]
+    async fn always_skips_online_check() {
+        set_online_override(Some(true));
+        let client = QueryClient::default();
+
+        let always = Query::new_with_opts(
+            || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+            QueryOpts::with_network_mode(NetworkMode::Always),
+        );
+        let before = checks();
+        client.fetch(&always).await;
+        assert_eq!(checks(), before);
+
+        let online = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        client.fetch(&online).await;
+        assert!(checks() > before);
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    fn initial_status_follows_network_mode() {
+        set_online_override(Some(false));
+        let client = QueryClient::default();
+
+        let always = Query::new_with_opts(
+            || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+            QueryOpts::with_network_mode(NetworkMode::Always),
+        );
+        let online = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let _always_guard = client.subscribe_query(&always, |_| {});
+        let _online_guard = client.subscribe_query(&online, |_| {});
+
+        assert!(matches!(
+            client.query_data(&always),
+            Some(QueryData::Pending(PendingStatus::Loading))
+        ));
+        assert!(matches!(
+            client.query_data(&online),
+            Some(QueryData::Pending(PendingStatus::Paused))
+        ));
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn offline_mutation_calls_on_no_connection() {
+        set_online_override(Some(false));
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v) })
+        });
+        let called = Rc::new(Cell::new(0_i32));
+        let callbacks = MutationCallbacks::<_, _, _, ()>::new()
+            .on_no_connection({
+                let called = Rc::clone(&called);
+                move |_, _| {
+                    called.set(called.get() + 1_i32);
+                    Box::pin(async {})
+                }
+            })
+            .on_settled({
+                let called = Rc::clone(&called);
+                move |_, _, _| {
+                    called.set(called.get() + 10_i32);
+                    Box::pin(async {})
+                }
+            });
+
+        let res = client.mutate(&mutation, 1_i32, None, Some(callbacks)).await;
+        assert!(matches!(res, Err(MutateError::NoConnection)));
+        assert_eq!(called.get(), 1_i32);
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn offline_optimistic_mutation_rolls_back() {
+        set_online_override(Some(false));
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v) })
+        });
+        client.set_query_data(&query, 1_i32);
+
+        let res = client
+            .mutate_optimistic(&mutation, 2_i32, &query, 2_i32, true)
+            .await;
+        assert!(matches!(res, Err(MutateError::NoConnection)));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Ok(r, _)) if *r == 1_i32
+        ));
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn no_connection_try_get() {
+        set_online_override(Some(false));
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+        let FetchResult::NoConnection(no_conn) = client.fetch(&query).await else {
+            panic!("fetch should wait for the connection");
+        };
+        let Err(mut no_conn) = no_conn.try_get() else {
+            panic!("deferred fetch shouldn't run while offline");
+        };
+
+        set_online_override(Some(true));
+        handler().check_online();
+        let result = loop {
+            match no_conn.try_get() {
+                Ok(result) => break result,
+                Err(pending) => no_conn = pending,
+            }
+            sleep(Duration::from_millis(1)).await;
+        };
+        assert!(matches!(result, FetchResultWaited::Fresh(Ok(d)) if *d == 1_i32));
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn removing_entry_cancels_offline_fetch() {
+        set_online_override(Some(false));
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+        let FetchResult::NoConnection(no_conn) = client.fetch(&query).await else {
+            panic!("fetch should wait for the connection");
+        };
+        drop(client.remove_query(&query));
+        assert!(matches!(
+            no_conn.wait().await,
+            FetchResultWaited::Cancelled(CancelReason::Evicted)
+        ));
+
+        set_online_override(None);
+    }
+
+    #[wasm_bindgen_test]
+    fn client_is_online() {
+        let client = QueryClient::default();
+
+        set_online_override(Some(false));
+        assert!(!client.is_online());
+        assert!(!handler().online());
+
+        set_online_override(Some(true));
+        assert!(client.is_online());
+        assert!(handler().online());
+
+        set_online_override(None);
+    }
+}
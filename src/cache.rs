@@ -1,4 +1,7 @@
-use std::{cell::Cell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use tokio::{select, sync::Notify};
 
@@ -26,7 +29,9 @@ pub(crate) trait Cacheable<'link> {
 }
 
 pub(crate) struct CacheControl<'func> {
-    active: Listenable<'func, bool>,
+    /// Behind a [`RefCell`] so activity can be toggled through a shared reference
+    /// Never borrowed across an await, and the listener doesn't touch it, so notifying can't double-borrow
+    active: RefCell<Listenable<'func, bool>>,
 }
 
 impl<'link> CacheControl<'link> {
@@ -35,8 +40,8 @@ impl<'link> CacheControl<'link> {
         cacheable: impl Cacheable<'link, LinkData = T> + Clone + 'link,
         cache_time: CacheTime,
     ) -> Self {
-        let mut this = Self {
-            active: Listenable::new(false),
+        let this = Self {
+            active: RefCell::new(Listenable::new(false)),
         };
 
         let CacheTime::Duration(dur) = cache_time else {
@@ -87,18 +92,104 @@ impl<'link> CacheControl<'link> {
         };
 
         handle_active(false);
-        this.active.add_listener_direct(Listener {
+        this.active.borrow_mut().add_listener_direct(Listener {
             f: Box::new(handle_active),
             drop_f: Some(Box::new(move || drop(fut_handle))),
+            alive: None,
         });
         this
     }
 
     pub(crate) fn active(&self) -> bool {
-        *self.active
+        **self.active.borrow()
+    }
+
+    pub(crate) fn set_active(&self, active: bool) {
+        Listenable::set_cmp(&mut self.active.borrow_mut(), active);
     }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::{mutation::MutationCache, query::QueryCache, CacheControl};
+    use crate::{
+        client::{ClientOpts, QueryClient},
+        config::CacheTime,
+        mutation::Mutation,
+        query::{Query, QueryOpts},
+        status::{MutationData, QueryData},
+        test_harness,
+    };
+
+    #[test]
+    fn set_active_shared() {
+        test_harness::run_local(async {
+            let client = QueryClient::default();
+            let query = Query::new_with_opts(
+                || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+                QueryOpts::with_cache_time(CacheTime::Infinite),
+            );
+            client.set_query_data(&query, 1_i32);
+
+            let control = Rc::new(CacheControl::new(
+                Rc::downgrade(client.query_cache()),
+                Rc::downgrade(&query.inner),
+                CacheTime::Duration(Duration::from_secs(5)),
+            ));
+            let other = Rc::clone(&control);
+
+            other.set_active(true);
+            assert!(control.active());
+            control.set_active(false);
+            assert!(!other.active());
 
-    pub(crate) fn set_active(&mut self, active: bool) {
-        Listenable::set_cmp(&mut self.active, active);
+            // Reactivated from elsewhere before the eviction fires
+            test_harness::advance(Duration::from_secs(4)).await;
+            other.set_active(true);
+            test_harness::advance(Duration::from_secs(2)).await;
+            assert!(client.query_data(&query).is_some());
+
+            other.set_active(false);
+            test_harness::advance(Duration::from_secs(6)).await;
+            assert!(client.query_data(&query).is_none());
+        });
+    }
+
+    #[test]
+    fn with_capacity() {
+        test_harness::run_local(async {
+            let mutation_cache = Rc::new(MutationCache::with_capacity(64));
+            let client = QueryClient::new_with_caches(
+                ClientOpts::new(),
+                Rc::new(QueryCache::with_capacity(64)),
+                Rc::clone(&mutation_cache),
+            );
+            assert!(client.query_cache().link_target.capacity() >= 64);
+            assert!(mutation_cache.link_target.capacity() >= 64);
+            assert_eq!(QueryCache::default().link_target.capacity(), 0);
+            let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+            let mutation = Mutation::new(|v: &i32| {
+                let v = *v;
+                Box::pin(async move { Ok::<i32, ()>(v) })
+            });
+
+            client.fetch(&query).await;
+            assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+
+            let _guard = client.subscribe_mutation(&mutation, |_| {});
+            drop(
+                client
+                    .mutate::<_, _, _, ()>(&mutation, 2_i32, None, None)
+                    .await,
+            );
+            assert!(
+                matches!(mutation_cache.data(&mutation), Some(MutationData::Ok(d)) if *d == 2_i32)
+            );
+
+            drop(client.query_cache().remove_query(&query));
+            assert!(client.query_data(&query).is_none());
+        });
     }
 }
@@ -4,6 +4,7 @@ use std::{
 };
 
 use crate::{
+    listenable::Listenable,
     mutation::{MutateMeta, Mutation},
     status::MutationData,
     weak_link::{Entry, Target, WeakLink},
@@ -16,8 +17,8 @@ pub struct MutationCache<'link> {
     pub(crate) link_target: Target<'link>,
 }
 
-impl<'link, R, E> Cache<'link, MutateMeta<'link, R, E>> for Weak<MutationCache<'link>> {
-    fn remove_cacheable(&self, link: &WeakLink<'link, MutateMeta<'link, R, E>>) {
+impl<'link, P, R, E> Cache<'link, MutateMeta<'link, P, R, E>> for Weak<MutationCache<'link>> {
+    fn remove_cacheable(&self, link: &WeakLink<'link, MutateMeta<'link, P, R, E>>) {
         if let Some(this) = self.upgrade() {
             this.remove_inner(link);
         }
@@ -38,11 +39,26 @@ impl Default for MutationCache<'_> {
     }
 }
 
+impl MutationCache<'_> {
+    /// Creates a cache with room for `capacity` mutations without reallocating
+    /// For clients that will use many mutations, use with [`QueryClient::new_with_caches`]
+    #[inline]
+    #[must_use = "Only used to create `MutationCache`, no effect if not used"]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            link_target: Target::with_capacity(capacity),
+        }
+    }
+}
+
 impl<'link> MutationCache<'link> {
     /// Gets the data for a given `mutation` in this cache
     #[inline]
     #[must_use = "Has no effect other than to clone the data into an ownable type, which you should use"]
-    pub fn data<P, R, E>(&self, mutation: &Mutation<'link, P, R, E>) -> Option<MutationData<R, E>> {
+    pub fn data<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+    ) -> Option<MutationData<P, R, E>> {
         mutation
             .inner
             .link
@@ -51,24 +67,30 @@ impl<'link> MutationCache<'link> {
     }
 
     /// Removes the cached data for a given `mutation` from this cache
+    /// Subscribers are notified with [`MutationData::default`] before being dropped along with the entry
     // Caller doesn't nessassarily want the actual data, just to remove the cached value
     #[allow(clippy::must_use_candidate)]
     #[inline]
     pub fn remove_mutation<P, R, E>(
         &self,
         mutation: &Mutation<'link, P, R, E>,
-    ) -> Option<MutationData<R, E>> {
+    ) -> Option<MutationData<P, R, E>> {
         self.remove_inner(&mutation.inner.link)
     }
 
     #[inline]
-    pub(crate) fn remove_inner<R, E>(
+    pub(crate) fn remove_inner<P, R, E>(
         &self,
-        link: &WeakLink<'link, MutateMeta<'link, R, E>>,
-    ) -> Option<MutationData<R, E>> {
+        link: &WeakLink<'link, MutateMeta<'link, P, R, E>>,
+    ) -> Option<MutationData<P, R, E>> {
         link.with_entry(&self.link_target, |e| match e {
             Entry::Vacant => None,
-            Entry::Occupied(o) => Some(o.remove().data.unwrap()),
+            Entry::Occupied(mut o) => {
+                // Listeners are dropped with the entry, let them know their data was reset first
+                let data = Listenable::set(&mut o.get_mut().data, MutationData::default());
+                drop(o.remove());
+                Some(data)
+            }
         })
     }
 }
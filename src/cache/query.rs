@@ -1,10 +1,15 @@
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Debug, Formatter},
-    rc::Weak,
+    rc::{Rc, Weak},
 };
 
 use crate::{
-    query::{FetchMeta, Query},
+    atomic_id,
+    listenable::Listenable,
+    ptr_hash::HashWeakPtr,
+    query::{FetchMeta, Query, QueryInner},
     status::QueryData,
     weak_link::{Entry, Target, WeakLink},
 };
@@ -12,8 +17,70 @@ use crate::{
 use super::Cache;
 
 /// Contains the cached data for queries in a [`QueryClient`]
+///
+/// Clients sharing it join each other's fetches of queries that take no param, or of a [`crate::query::KeyedQuery`] key
+/// Fetches of queries with a param aren't joined, as a different arg may give different data
 pub struct QueryCache<'link> {
     pub(crate) link_target: Target<'link>,
+    /// Queries that have been used with this cache, so the least recently used can be evicted
+    recency: RefCell<Recency<'link>>,
+}
+
+type CachedQueryPtr<'link> = HashWeakPtr<dyn CachedQuery<'link> + 'link>;
+
+/// Order queries were last used in, keyed by an increasing id so the first is the least recent
+#[derive(Default)]
+struct Recency<'link> {
+    order: BTreeMap<usize, CachedQueryPtr<'link>>,
+    used: HashMap<CachedQueryPtr<'link>, usize>,
+}
+
+impl<'link> Recency<'link> {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            order: BTreeMap::new(),
+            used: HashMap::with_capacity(capacity),
+        }
+    }
+
+    fn touch(&mut self, query: CachedQueryPtr<'link>) {
+        let id = atomic_id::next();
+        if let Some(prev) = self.used.insert(query.clone(), id) {
+            self.order.remove(&prev);
+        }
+        self.order.insert(id, query);
+    }
+
+    fn forget(&mut self, query: &CachedQueryPtr<'link>) {
+        if let Some(prev) = self.used.remove(query) {
+            self.order.remove(&prev);
+        }
+    }
+}
+
+/// Type-erased [`QueryInner`]
+pub(crate) trait CachedQuery<'link> {
+    /// Whether this query has an entry in `cache`
+    fn is_cached(&self, cache: &QueryCache<'link>) -> bool;
+    /// Removes the entry for this query from `cache` if it's inactive, returning whether it was removed
+    fn evict_inactive(&self, cache: &QueryCache<'link>) -> bool;
+}
+
+impl<'link, P, R, E> CachedQuery<'link> for QueryInner<'link, P, R, E> {
+    fn is_cached(&self, cache: &QueryCache<'link>) -> bool {
+        self.link.borrow(&cache.link_target).is_some()
+    }
+
+    fn evict_inactive(&self, cache: &QueryCache<'link>) -> bool {
+        let inactive = self
+            .link
+            .borrow(&cache.link_target)
+            .is_some_and(|e| !e.cache_control.active());
+        if inactive {
+            drop(cache.remove_inner(&self.link));
+        }
+        inactive
+    }
 }
 
 impl Debug for QueryCache<'_> {
@@ -26,6 +93,20 @@ impl Default for QueryCache<'_> {
     fn default() -> Self {
         Self {
             link_target: Target::new(),
+            recency: RefCell::new(Recency::default()),
+        }
+    }
+}
+
+impl QueryCache<'_> {
+    /// Creates a cache with room for `capacity` queries without reallocating
+    /// For clients that will use many queries, use with [`QueryClient::new_with_caches`]
+    #[inline]
+    #[must_use = "Only used to create `QueryCache`, no effect if not used"]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            link_target: Target::with_capacity(capacity),
+            recency: RefCell::new(Recency::with_capacity(capacity)),
         }
     }
 }
@@ -52,6 +133,7 @@ impl<'link> QueryCache<'link> {
     }
 
     /// Removes the cached data for a given `query` from this cache
+    /// Subscribers are notified with [`QueryData::default`] before being dropped along with the entry
     // Caller doesn't nessassarily want the actual data, just to remove the cached value
     #[allow(clippy::must_use_candidate)]
     #[inline]
@@ -66,7 +148,52 @@ impl<'link> QueryCache<'link> {
     ) -> Option<QueryData<R, E>> {
         link.with_entry(&self.link_target, |e| match e {
             Entry::Vacant => None,
-            Entry::Occupied(o) => Some(o.remove().data.unwrap()),
+            Entry::Occupied(mut o) => {
+                // Listeners are dropped with the entry, let them know their data was reset first
+                let data = Listenable::set(&mut o.get_mut().data, QueryData::default());
+                drop(o.remove());
+                Some(data)
+            }
         })
     }
+
+    /// Marks `query` as the most recently used
+    pub(crate) fn touch<P, R, E>(&self, query: &Rc<QueryInner<'link, P, R, E>>) {
+        self.recency.borrow_mut().touch(Self::cached_ptr(query));
+    }
+
+    /// Removes least recently used inactive entries until there are at most `max`, or only active ones remain
+    /// `creating` is the query whose entry is being created, its link is already borrowed so it's skipped
+    pub(crate) fn evict_lru<P, R, E>(&self, max: usize, creating: &Rc<QueryInner<'link, P, R, E>>) {
+        let creating = Self::cached_ptr(creating);
+        // Not borrowed while evicting, removing an entry notifies its listeners which may use this cache
+        let queries: Vec<_> = self.recency.borrow().order.values().cloned().collect();
+        let mut cached = Vec::with_capacity(queries.len());
+        for ptr in queries {
+            if ptr == creating {
+                continue;
+            }
+            match ptr.upgrade() {
+                Some(query) if query.is_cached(self) => cached.push((ptr, query)),
+                Some(_) | None => self.recency.borrow_mut().forget(&ptr),
+            }
+        }
+
+        let mut excess = cached.len().saturating_sub(max);
+        for (ptr, query) in cached {
+            if excess == 0 {
+                break;
+            }
+            if query.evict_inactive(self) {
+                self.recency.borrow_mut().forget(&ptr);
+                excess -= 1;
+            }
+        }
+    }
+
+    fn cached_ptr<P, R, E>(query: &Rc<QueryInner<'link, P, R, E>>) -> CachedQueryPtr<'link> {
+        // Non-trivial
+        #[allow(trivial_casts)]
+        HashWeakPtr(Rc::downgrade(query) as Weak<dyn CachedQuery<'link> + 'link>)
+    }
 }
@@ -5,32 +5,48 @@
 
 use std::{
     cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
     fmt::{self, Debug, Formatter},
-    rc::Rc,
+    future::Future,
+    pin::{pin, Pin},
+    rc::{Rc, Weak},
+    task::{Context, Poll},
     time::Duration,
 };
 
-use tokio::sync::Notify;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    future, poll, Stream, StreamExt,
+};
+use tokio::{select, sync::Notify};
 
 use crate::{
     atomic_id,
     cache::{mutation::MutationCache, query::QueryCache, CacheControl},
     config::{
         error::Error,
-        resolve::{self, ConfigOption},
+        resolve::{self, ResolvedOpts},
         retry::RetryConfig,
         CacheTime, NetworkMode, SetOption,
     },
     const_default::ConstDefault,
-    futures::future_handle,
+    futures::{
+        future_handle::{self, CleanupHandle, CleanupOn, FutureHandle},
+        yield_now,
+    },
     handle_map::HandleMap,
-    listenable::Listenable,
+    listenable::{Listenable, Listener},
+    metrics::FetchMetrics,
     mutation::{MutateMeta, Mutation, MutationCallbacks, MutationOpts},
-    query::{FetchMeta, Query, QueryOpts},
+    ptr_hash::HashWeakPtr,
+    query::{
+        FetchFuture, FetchMeta, Joined, KeyedQuery, KeyedQueryInner, LeadFetch, Query,
+        QueryDescriptor, QueryInner, QueryKey, QueryOpts, RefetchFn, SharedFetch,
+    },
     sleep,
     status::{
-        FetchResult, FetchResultWaited, MutateError, MutationData, NoConnection, NoConnectionInner,
-        PendingStatus, QueryData, QueryStatus,
+        CancelReason, FetchResult, FetchResultWaited, Instant, MutateError, MutationData,
+        NoConnection, NoConnectionInner, PendingStatus, QueryData, QueryPeek, QueryStatus,
     },
     weak_link::Entry,
 };
@@ -39,8 +55,11 @@ use crate::{
 #[cfg(not(target_arch = "wasm32"))]
 pub mod engine;
 
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests;
+
 /// Configuration options for this client
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct ClientOpts<'cfg> {
     /// See [`CacheTime`]
     pub cache_time: SetOption<CacheTime>,
@@ -48,10 +67,19 @@ pub struct ClientOpts<'cfg> {
     pub network_mode: SetOption<NetworkMode>,
     /// See [`RetryConfig`]
     pub retry: SetOption<RetryConfig<'cfg, dyn Error + 'cfg>>,
+    /// See [`QueryOpts.cache_errors`]
+    pub cache_errors: SetOption<bool>,
     /// Default options for queries executed on this client
     pub query: Option<QueryOpts<'cfg, dyn Error + 'cfg>>,
     /// Default options for mutations executed on this client
     pub mutation: Option<MutationOpts<'cfg, dyn Error + 'cfg>>,
+    /// Maximum number of queries kept in the cache, the least recently used inactive ones are removed first to make room
+    /// Active queries are never removed, so there may be more than this while they're in use
+    /// Default: no limit
+    pub max_cached_queries: Option<usize>,
+    /// Fetches yield to other tasks before starting while more than this many are in progress, so launching many at once doesn't starve the event loop
+    /// Default: never yield
+    pub yield_after_fetches: Option<usize>,
 }
 
 impl<'cfg> From<QueryOpts<'cfg, dyn Error + 'cfg>> for ClientOpts<'cfg> {
@@ -60,6 +88,7 @@ impl<'cfg> From<QueryOpts<'cfg, dyn Error + 'cfg>> for ClientOpts<'cfg> {
             cache_time: value.cache_time,
             network_mode: value.network_mode,
             retry: value.retry,
+            cache_errors: value.cache_errors,
             ..Default::default()
         }
     }
@@ -76,6 +105,31 @@ impl<'cfg> From<MutationOpts<'cfg, dyn Error + 'cfg>> for ClientOpts<'cfg> {
     }
 }
 
+impl From<CacheTime> for ClientOpts<'_> {
+    fn from(value: CacheTime) -> Self {
+        Self::with_cache_time(value)
+    }
+}
+
+impl From<NetworkMode> for ClientOpts<'_> {
+    fn from(value: NetworkMode) -> Self {
+        Self::with_network_mode(value)
+    }
+}
+
+impl<'cfg> From<RetryConfig<'cfg, dyn Error + 'cfg>> for ClientOpts<'cfg> {
+    fn from(value: RetryConfig<'cfg, dyn Error + 'cfg>) -> Self {
+        Self::with_retry(value)
+    }
+}
+
+// Not derived, that would set `cache_errors` to `bool::default()`, so errors wouldn't be cached by default
+impl Default for ClientOpts<'_> {
+    fn default() -> Self {
+        Self::const_default()
+    }
+}
+
 impl ConstDefault for ClientOpts<'_> {
     const DEFAULT: Self = Self::const_default();
 }
@@ -89,8 +143,11 @@ impl<'cfg> ClientOpts<'cfg> {
             cache_time: SetOption::Inherrit,
             network_mode: SetOption::Inherrit,
             retry: SetOption::Inherrit,
+            cache_errors: SetOption::Inherrit,
             query: None,
             mutation: None,
+            max_cached_queries: None,
+            yield_after_fetches: None,
         }
     }
 
@@ -102,11 +159,35 @@ impl<'cfg> ClientOpts<'cfg> {
             cache_time: SetOption::DEFAULT,
             network_mode: SetOption::DEFAULT,
             retry: SetOption::DEFAULT,
+            cache_errors: SetOption::Set(true),
             query: None,
             mutation: None,
+            max_cached_queries: None,
+            yield_after_fetches: None,
         }
     }
 
+    /// New options that inherrit all except [`ClientOpts.cache_time`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_cache_time(cache_time: CacheTime) -> Self {
+        Self::new().set_cache_time(cache_time)
+    }
+
+    /// New options that inherrit all except [`ClientOpts.network_mode`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_network_mode(network_mode: NetworkMode) -> Self {
+        Self::new().set_network_mode(network_mode)
+    }
+
+    /// New options that inherrit all except [`ClientOpts.retry`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub fn with_retry(retry: RetryConfig<'cfg, dyn Error + 'cfg>) -> Self {
+        Self::new().set_retry(retry)
+    }
+
     /// Sets [`ClientOpts.cache_time`]
     #[must_use = "Builder pattern"]
     #[inline]
@@ -149,11 +230,59 @@ impl<'cfg> ClientOpts<'cfg> {
         self.mutation = Some(mutation.into());
         self
     }
+
+    /// Sets [`ClientOpts.cache_errors`]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub const fn set_cache_errors(mut self, cache_errors: bool) -> Self {
+        self.cache_errors = SetOption::set(cache_errors);
+        self
+    }
+
+    /// Sets [`ClientOpts.max_cached_queries`]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub const fn set_max_cached_queries(mut self, max: usize) -> Self {
+        self.max_cached_queries = Some(max);
+        self
+    }
+
+    /// Sets [`ClientOpts.yield_after_fetches`]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub const fn set_yield_after_fetches(mut self, max: usize) -> Self {
+        self.yield_after_fetches = Some(max);
+        self
+    }
+
+    /// Layers `other` over these options, anything set in `other` is used in place of the value here
+    /// Default query & mutation options set in both are merged the same way
+    #[must_use = "Has no effect other than to return the merged options"]
+    #[inline]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cache_time: self.cache_time.merge(other.cache_time),
+            network_mode: self.network_mode.merge(other.network_mode),
+            retry: self.retry.merge(other.retry),
+            cache_errors: self.cache_errors.merge(other.cache_errors),
+            query: match (self.query, other.query) {
+                (Some(query), Some(other)) => Some(query.merge(other)),
+                (query, other) => other.or(query),
+            },
+            mutation: match (self.mutation, other.mutation) {
+                (Some(mutation), Some(other)) => Some(mutation.merge(other)),
+                (mutation, other) => other.or(mutation),
+            },
+            max_cached_queries: other.max_cached_queries.or(self.max_cached_queries),
+            yield_after_fetches: other.yield_after_fetches.or(self.yield_after_fetches),
+        }
+    }
 }
 
 /// A client that can be configured and used to execute queries and mutations, and cache their results
 pub struct QueryClient<'link> {
     inner: Rc<QueryClientInner<'link>>,
+    spawned: Rc<SpawnedFutures<'link>>,
 }
 
 impl Debug for QueryClient<'_> {
@@ -167,8 +296,205 @@ impl Debug for QueryClient<'_> {
 
 struct QueryClientInner<'link> {
     opts: ClientOpts<'link>,
+    resolved: ResolvedOpts,
     pub(crate) query_cache: Rc<QueryCache<'link>>,
     pub(crate) mutation_cache: Rc<MutationCache<'link>>,
+    spawned: Weak<SpawnedFutures<'link>>,
+    /// Each [`KeyedQuery`] used with this client, so their keys can be found by [`QueryClient::invalidate_where`]
+    keyed: RefCell<HashSet<HashWeakPtr<dyn InvalidateKeyed<'link> + 'link>>>,
+    /// Each [`Query`] that's had an entry in this client's cache, so they can be found by [`QueryClient::clear_errors`] & [`QueryClient::active_query_ids`]
+    queries: RefCell<HashSet<HashWeakPtr<dyn ClientQuery<'link> + 'link>>>,
+    /// Notified by [`QueryClient::cancel_all`], cancelling every fetch in progress
+    cancel_all: Notify,
+    /// Number of fetches in progress, see [`ClientOpts::yield_after_fetches`]
+    in_flight: Cell<usize>,
+    /// Notified when the last fetch in progress finishes, see [`QueryClient::wait_for_idle`]
+    idle: Notify,
+}
+
+/// Counts a fetch as in progress on its client until dropped
+struct InFlight<'client, 'link> {
+    client: &'client QueryClientInner<'link>,
+}
+
+impl<'client, 'link> InFlight<'client, 'link> {
+    fn start(client: &'client QueryClientInner<'link>) -> Self {
+        client.in_flight.set(client.in_flight.get() + 1);
+        Self { client }
+    }
+}
+
+impl Drop for InFlight<'_, '_> {
+    fn drop(&mut self) {
+        let in_flight = self.client.in_flight.get() - 1;
+        self.client.in_flight.set(in_flight);
+        if in_flight == 0 {
+            self.client.idle.notify_waiters();
+        }
+    }
+}
+
+/// Argument of a fetch, borrowed from the caller unless it has to be owned
+enum FetchArg<'arg, P> {
+    /// Shared with the entry's refetch, see [`FetchMeta::refetch`]
+    Shared(Rc<P>),
+    /// Converted to owned with the function, only if the fetch has to continue without the caller once the connection returns
+    Borrowed(&'arg P, fn(&P) -> P),
+}
+
+impl<P> FetchArg<'_, P> {
+    fn get(&self) -> &P {
+        match *self {
+            Self::Shared(ref arg) => arg,
+            Self::Borrowed(arg, _) => arg,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn into_shared(self) -> Rc<P> {
+        match self {
+            Self::Shared(arg) => arg,
+            Self::Borrowed(arg, to_owned) => Rc::new(to_owned(arg)),
+        }
+    }
+}
+
+/// Type-erased [`QueryInner`]
+trait ClientQuery<'link> {
+    /// See [`QueryClient::clear_errors`]
+    fn clear_error(&self, client: &QueryClient<'link>);
+
+    /// Id of this query's entry, if it has one that's active, see [`QueryClient::active_query_ids`]
+    fn active_id(&self, client: &QueryClient<'link>) -> Option<usize>;
+
+    /// Supersede any fetch in progress for this query's entry, see [`QueryClient::cancel_all`]
+    fn cancel(&self, client: &QueryClient<'link>);
+
+    /// Key & serialized data of this query's entry, if it's persistable & has data, see [`QueryClient::persist_all`]
+    fn persist(&self, client: &QueryClient<'link>) -> Option<(String, String)>;
+}
+
+impl<'link, P, R, E> ClientQuery<'link> for QueryInner<'link, P, R, E> {
+    fn active_id(&self, client: &QueryClient<'link>) -> Option<usize> {
+        self.link
+            .borrow(&client.inner.query_cache.link_target)
+            .and_then(|e| e.cache_control.active().then_some(e.entry_id))
+    }
+
+    fn cancel(&self, client: &QueryClient<'link>) {
+        self.link
+            .with_entry(&client.inner.query_cache.link_target, |e| match e {
+                Entry::Occupied(mut o) => {
+                    let entry = o.get_mut();
+                    entry.id = atomic_id::next();
+                    entry.settle_cancelled();
+                }
+                Entry::Vacant => {}
+            });
+    }
+
+    fn persist(&self, client: &QueryClient<'link>) -> Option<(String, String)> {
+        let persist = self.persist.as_ref()?;
+        let entry = self.link.borrow(&client.inner.query_cache.link_target)?;
+        match *entry.data {
+            QueryData::Ok(ref data, _) => {
+                (persist.serialize)(data).map(|data| (persist.key.clone(), data))
+            }
+            QueryData::Err(..) | QueryData::Pending(_) => None,
+        }
+    }
+
+    fn clear_error(&self, client: &QueryClient<'link>) {
+        let cache = &client.inner.query_cache;
+        let action = self.link.with_entry(&cache.link_target, |e| match e {
+            Entry::Occupied(mut o) if matches!(*o.get().data, QueryData::Err(..)) => {
+                let entry = o.get_mut();
+                if !entry.cache_control.active() {
+                    return Some(None);
+                }
+
+                // Without an arg it was fetched with it can't be refetched, so keep the error rather than be stuck loading
+                let refetch = entry.refetch.clone()?;
+                entry.set_data(QueryData::Pending(PendingStatus::Loading));
+                Some(Some(refetch))
+            }
+            Entry::Occupied(_) | Entry::Vacant => None,
+        });
+
+        match action {
+            Some(Some(refetch)) => client.spawn_fetch(refetch(client)),
+            Some(None) => drop(cache.remove_inner(&self.link)),
+            None => {}
+        }
+    }
+}
+
+/// Type-erased [`KeyedQueryInner`]
+trait InvalidateKeyed<'link> {
+    fn invalidate_where(
+        &self,
+        client: &QueryClient<'link>,
+        predicate: &dyn Fn(&QueryDescriptor<'_>) -> bool,
+    );
+}
+
+impl<'link, K: QueryKey, R: 'link, E: Error + 'link> InvalidateKeyed<'link>
+    for KeyedQueryInner<'link, K, R, E>
+{
+    fn invalidate_where(
+        &self,
+        client: &QueryClient<'link>,
+        predicate: &dyn Fn(&QueryDescriptor<'_>) -> bool,
+    ) {
+        let matching: Vec<_> = self
+            .keys
+            .borrow()
+            .iter()
+            .filter(|&(key, _)| {
+                predicate(&QueryDescriptor {
+                    name: self.name.as_deref(),
+                    key,
+                })
+            })
+            .map(|(key, query)| (key.clone(), query.clone()))
+            .collect();
+
+        for (key, query) in matching {
+            client.invalidate_with_arg(&query, key);
+        }
+    }
+}
+
+/// Context for the callbacks of [`QueryClient::mutate_optimistic`]
+struct Optimistic<'link, P, R, E> {
+    client: QueryClient<'link>,
+    query: Query<'link, P, R, E>,
+    /// Data of `query` from before the optimistic data was set
+    snapshot: Option<QueryData<R, E>>,
+}
+
+impl<'link, P: 'link, R: 'link, E: 'link> Optimistic<'link, P, R, E> {
+    /// Restores the data of `query` from before the optimistic data was set
+    fn rollback(&self) {
+        match self.snapshot {
+            Some(ref snapshot) => self.client.set_query_state(&self.query, snapshot.clone()),
+            None => drop(self.client.inner.query_cache.remove_query(&self.query)),
+        }
+    }
+}
+
+/// Futures spawned by a [`QueryClient`]
+/// Owned only by the clones of the client and not by the futures themselves, so they're aborted once the last clone is dropped
+struct SpawnedFutures<'link> {
+    handles: RefCell<HandleMap<FutureHandle<'link>>>,
+}
+
+impl SpawnedFutures<'_> {
+    const fn new() -> Self {
+        Self {
+            handles: RefCell::new(HandleMap::new()),
+        }
+    }
 }
 
 impl Default for QueryClient<'_> {
@@ -183,8 +509,200 @@ impl Clone for QueryClient<'_> {
     fn clone(&self) -> Self {
         Self {
             inner: Rc::clone(&self.inner),
+            spawned: Rc::clone(&self.spawned),
+        }
+    }
+}
+
+/// Subscription to a query or mutation, or retention of a query, released when dropped
+#[must_use = "dropping the Guard immediately unsubscribes"]
+pub struct Guard<'link> {
+    unsubscribe: Option<Box<dyn FnOnce() + 'link>>,
+}
+
+impl Debug for Guard<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Guard").finish_non_exhaustive()
+    }
+}
+
+impl<'link> Guard<'link> {
+    fn new(unsubscribe: impl FnOnce() + 'link) -> Self {
+        Self {
+            unsubscribe: Some(Box::new(unsubscribe)),
         }
     }
+
+    /// Keep the subscription or retention for as long as the client & query or mutation exist, rather than until dropped
+    #[inline]
+    pub fn leak(mut self) {
+        // Leaked rather than dropped, as it can own what's being kept, such as the guards of `subscribe_many`
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            Box::leak(unsubscribe);
+        }
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            unsubscribe();
+        }
+    }
+}
+
+/// Stream of the values sent by a subscription, holding its [`Guard`] so it unsubscribes when dropped
+struct GuardedStream<'link, T> {
+    rx: UnboundedReceiver<T>,
+    _guard: Guard<'link>,
+}
+
+impl<T> Stream for GuardedStream<'_, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_next_unpin(cx)
+    }
+}
+
+/// Subscriptions to queries with any argument, result & error types, to be released together, see [`QueryClient::subscribe_many`]
+#[must_use = "Every subscription is released when dropped, use `guard` to keep them"]
+pub struct SubscribeMany<'client, 'link> {
+    client: &'client QueryClient<'link>,
+    guards: Vec<Guard<'link>>,
+}
+
+impl Debug for SubscribeMany<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscribeMany")
+            .field("guards", &self.guards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'link> SubscribeMany<'_, 'link> {
+    /// Subscribes to changes of the data for `query`, see [`QueryClient::subscribe_query`]
+    pub fn add<P, R, E>(
+        mut self,
+        query: &Query<'link, P, R, E>,
+        f: impl Fn(QueryData<R, E>) + 'link,
+    ) -> Self {
+        self.guards.push(self.client.subscribe_query(query, f));
+        self
+    }
+
+    /// A single [`Guard`] for every added subscription, releasing them all when dropped
+    pub fn guard(self) -> Guard<'link> {
+        let guards = self.guards;
+        Guard::new(move || drop(guards))
+    }
+}
+
+/// Cancels a group of fetches & subscriptions at once, such as everything started for a route
+/// Clones share the same state, so one can be tripped while others are held by what it cancels
+#[derive(Clone, Default)]
+pub struct CancelToken<'link> {
+    inner: Rc<CancelTokenInner<'link>>,
+}
+
+#[derive(Default)]
+struct CancelTokenInner<'link> {
+    cancelled: Cell<bool>,
+    notify: Notify,
+    guards: RefCell<Vec<Guard<'link>>>,
+}
+
+impl Debug for CancelToken<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.inner.cancelled.get())
+            .field("guards", &self.inner.guards.borrow().len())
+            .finish()
+    }
+}
+
+impl<'link> CancelToken<'link> {
+    /// New token that hasn't been cancelled
+    #[must_use = "No reason to create a CancelToken if you don't use it"]
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every fetch waiting on [`Self::cancelled`] & releases every held [`Guard`]
+    /// Only has an effect the first time
+    pub fn cancel(&self) {
+        if self.inner.cancelled.replace(true) {
+            return;
+        }
+        self.inner.notify.notify_waiters();
+        // Taken first, so unsubscribing can't find the guards borrowed
+        let guards = self.inner.guards.take();
+        drop(guards);
+    }
+
+    /// Whether [`Self::cancel`] has been called on this token or one of its clones
+    #[must_use = "Has no effect other than to return whether it's cancelled"]
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.get()
+    }
+
+    /// Completes once this token is cancelled, straight away if it already is
+    /// Pass to [`QueryClient::fetch_with_cancel`] to tie a fetch to this token
+    pub async fn cancelled(&self) {
+        // Created before checking, so cancelling in between can't be missed
+        let notified = self.inner.notify.notified();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+
+    /// Holds `guard` until this token is cancelled, releasing it straight away if it already is
+    pub fn hold(&self, guard: Guard<'link>) {
+        if self.is_cancelled() {
+            drop(guard);
+        } else {
+            self.inner.guards.borrow_mut().push(guard);
+        }
+    }
+}
+
+/// Fetches of queries with any argument, result & error types, to be run together, see [`QueryClient::prefetch_all`]
+#[must_use = "Nothing is fetched until `run` is awaited"]
+pub struct PrefetchAll<'link> {
+    client: QueryClient<'link>,
+    fetches: Vec<Pin<Box<dyn Future<Output = ()> + 'link>>>,
+}
+
+impl Debug for PrefetchAll<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrefetchAll")
+            .field("fetches", &self.fetches.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'link> PrefetchAll<'link> {
+    /// Adds a fetch of `query` with `arg`
+    pub fn add<P: 'link, R: 'link, E: Error + 'link>(
+        mut self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+    ) -> Self {
+        let client = self.client.clone();
+        let query = query.clone();
+        self.fetches.push(Box::pin(async move {
+            client.fetch_with_arg(&query, arg).await;
+        }));
+        self
+    }
+
+    /// Fetches every added query at once, finishing once they all have
+    /// The results are in the cache, the same as if each was fetched individually
+    pub async fn run(self) {
+        future::join_all(self.fetches).await;
+    }
 }
 
 impl<'link> QueryClient<'link> {
@@ -199,6 +717,15 @@ impl<'link> QueryClient<'link> {
         )
     }
 
+    /// Create a new [`QueryClient`] with [`ClientOpts::const_default`], every option set to its concrete default
+    /// [`Self::default`] instead inherrits every option, falling back to the defaults only when resolving them
+    /// They behave the same as long as those are the only defaults, but options set here take priority over anything a client would inherrit
+    #[inline]
+    #[must_use = "Only used to create `QueryClient`, no effect if not used"]
+    pub fn default_with_opts() -> Self {
+        Self::new(ClientOpts::const_default())
+    }
+
     /// Create a [`QueryClient`] with provided options, and attached to a given [`QueryCache`]
     #[inline]
     #[must_use = "Only used to create `QueryClient`, no effect if not used"]
@@ -207,15 +734,43 @@ impl<'link> QueryClient<'link> {
         query_cache: Rc<QueryCache<'link>>,
         mutation_cache: Rc<MutationCache<'link>>,
     ) -> Self {
+        let spawned = Rc::new(SpawnedFutures::new());
+        let opts = opts.into();
+
         Self {
             inner: Rc::new(QueryClientInner {
-                opts: opts.into(),
+                resolved: ResolvedOpts::new(&opts),
+                opts,
                 query_cache,
                 mutation_cache,
+                spawned: Rc::downgrade(&spawned),
+                keyed: RefCell::new(HashSet::new()),
+                queries: RefCell::new(HashSet::new()),
+                cancel_all: Notify::new(),
+                in_flight: Cell::new(0),
+                idle: Notify::new(),
             }),
+            spawned,
+        }
+    }
+
+    /// Client for futures this client spawns, which share its inner client but not its spawned futures
+    /// Holding it doesn't stop them being aborted once every clone of this client is dropped
+    fn detached(inner: Rc<QueryClientInner<'link>>) -> Self {
+        Self {
+            inner,
+            spawned: Rc::new(SpawnedFutures::new()),
         }
     }
 
+    /// Whether the browser currently reports having a connection
+    #[cfg(target_arch = "wasm32")]
+    #[must_use = "Has no effect other than to check the connection"]
+    #[inline]
+    pub fn is_online(&self) -> bool {
+        crate::browser::online_handler::is_online()
+    }
+
     /// Get [`QueryCache`] this client is attached to
     #[inline]
     #[must_use = "Only gets `QueryCache`, not effect if not used"]
@@ -223,195 +778,688 @@ impl<'link> QueryClient<'link> {
         &self.inner.query_cache
     }
 
+    /// Get [`MutationCache`] this client is attached to
+    #[inline]
+    #[must_use = "Only gets `MutationCache`, not effect if not used"]
+    pub fn mutation_cache(&self) -> &Rc<MutationCache<'link>> {
+        &self.inner.mutation_cache
+    }
+
+    /// Removes the cached data for a given `query` from this client, see [`QueryCache::remove_query`]
+    // Caller doesn't nessassarily want the actual data, just to remove the cached value
+    #[allow(clippy::must_use_candidate)]
+    #[inline]
+    pub fn remove_query<P, R, E>(&self, query: &Query<'link, P, R, E>) -> Option<QueryData<R, E>> {
+        self.inner.query_cache.remove_query(query)
+    }
+
+    /// Removes the cached data for a given `mutation` from this client, see [`MutationCache::remove_mutation`]
+    // Caller doesn't nessassarily want the actual data, just to remove the cached value
+    #[allow(clippy::must_use_candidate)]
+    #[inline]
+    pub fn remove_mutation<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+    ) -> Option<MutationData<P, R, E>> {
+        self.inner.mutation_cache.remove_mutation(mutation)
+    }
+
+    /// Resets the cached data for a given `query` on this client back to [`QueryData::default`], notifying subscribers
+    /// Unlike [`Self::remove_query`], the entry & its subscribers are kept
+    /// Doesn't refetch, see [`Self::reset_query_refetch`]
+    // Caller doesn't nessassarily want the previous data, just to reset it
+    #[allow(clippy::must_use_candidate)]
+    pub fn reset_query<P, R, E>(&self, query: &Query<'link, P, R, E>) -> Option<QueryData<R, E>> {
+        query
+            .inner
+            .link
+            .with_entry(&self.inner.query_cache.link_target, |e| match e {
+                Entry::Vacant => None,
+                Entry::Occupied(mut o) => Some(o.get_mut().set_data(QueryData::default())),
+            })
+    }
+
+    /// Resets the cached data for a given `query` on this client, see [`Self::reset_query`]
+    /// If the query is active, it's then refetched in the background, see [`Self::prefetch`]
+    pub fn reset_query_refetch<R: 'link, E: Error + 'link>(&self, query: &Query<'link, (), R, E>) {
+        drop(self.reset_query(query));
+
+        let active = query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .is_some_and(|e| e.cache_control.active());
+        if active {
+            self.prefetch(query);
+        }
+    }
+
+    fn new_fetch_meta<P, R, E>(&self, query: &Query<'link, P, R, E>) -> FetchMeta<'link, R, E> {
+        // Non-trivial
+        #[allow(trivial_casts)]
+        self.inner.queries.borrow_mut().insert(HashWeakPtr(
+            Rc::downgrade(&query.inner) as Weak<dyn ClientQuery<'link> + 'link>
+        ));
+        if let Some(max) = self.inner.opts.max_cached_queries {
+            // Make room for the new entry, which isn't in the cache yet
+            self.inner
+                .query_cache
+                .evict_lru(max.saturating_sub(1), &query.inner);
+        }
+        self.inner.touch_query(&query.inner);
+        let cache_time = self.inner.resolved.cache_time(&query.inner.opts);
+        let network_mode = self.inner.resolved.network_mode(&query.inner.opts);
+
+        FetchMeta {
+            data: Listenable::new(QueryData::Pending(PendingStatus::get_for(network_mode))),
+            id: atomic_id::next(),
+            entry_id: atomic_id::next(),
+            cache_control: CacheControl::new(
+                Rc::downgrade(&self.inner.query_cache),
+                Rc::downgrade(&query.inner),
+                cache_time,
+            ),
+            metrics: FetchMetrics::default(),
+            refetch: None,
+            retained: 0,
+            data_updated_at: None,
+            error_updated_at: None,
+            shared_fetch: Weak::new(),
+            future_handles: HandleMap::new(),
+        }
+    }
+
     #[inline]
-    pub(crate) async fn fetch_with_arg<P, R, E: Error>(
+    pub(crate) async fn fetch_with_arg<P: 'link, R: 'link, E: Error + 'link>(
         &self,
         query: &Query<'link, P, R, E>,
         arg: P,
     ) -> FetchResult<R, E> {
+        if query.inner.takes_no_param() {
+            return self.fetch_or_join(query, Rc::new(arg)).await;
+        }
+        if self.inner.resolved.no_cache(&query.inner.opts) {
+            return self.inner.fetch_uncached(&query.inner, &arg).await;
+        }
+
+        let arg = Rc::new(arg);
         let id = query.inner.link.with_or_else(
             &self.inner.query_cache.link_target,
-            || {
-                let cache_time: CacheTime = resolve::resolve_option(
-                    ConfigOption::CacheTime,
-                    &self.inner.opts,
-                    &query.inner.opts,
-                );
-
-                FetchMeta {
-                    data: Listenable::new(QueryData::default()),
-                    id: atomic_id::next(),
-                    future_handles: HandleMap::new(),
-                    cache_control: CacheControl::new(
-                        Rc::downgrade(&self.inner.query_cache),
-                        Rc::downgrade(&query.inner),
-                        cache_time,
-                    ),
-                }
+            || self.new_fetch_meta(query),
+            |e| {
+                e.refetch = Some(Self::refetch_fn(query, Rc::clone(&arg)));
+                e.id
             },
-            |e| e.id,
         );
 
         return Rc::clone(&self.inner)
-            .fetch_with_arg_inner(Rc::clone(&query.inner), arg, id, 1)
+            .fetch_with_arg_inner(Rc::clone(&query.inner), FetchArg::Shared(arg), id, 1)
             .await;
     }
 
-    /// Execute mutation on this [`QueryClient`]
-    ///
-    /// # Errors
-    /// Will error if the mutation function errors
-    ///
-    /// # Panics
-    /// Will always panic on engine-side
-    #[cfg(not(target_arch = "wasm32"))]
-    pub async fn mutate<P, R, E, C>(
-        &self,
-        _mutation: &Mutation<'link, P, R, E>,
-        _value: P,
-        _default_cb: Option<&MutationCallbacks<P, R, E, C>>,
-        _cb: Option<MutationCallbacks<P, R, E, C>>,
-    ) -> Result<Rc<R>, MutateError<E>> {
-        panic!("Should not mutate on the engine");
+    /// Refetches `query` with `arg`, recorded on its entry as the last arg it was fetched with, see [`FetchMeta::refetch`]
+    pub(crate) fn refetch_fn<P: 'link, R: 'link, E: Error + 'link>(
+        query: &Query<'link, P, R, E>,
+        arg: Rc<P>,
+    ) -> RefetchFn<'link, R, E> {
+        // Weak, the entry mustn't keep its own query alive
+        let weak = Rc::downgrade(&query.inner);
+        Rc::new(move |client: &Self| match weak.upgrade() {
+            Some(inner) => client.start_fetch(&Query { inner }, Rc::clone(&arg)),
+            None => Box::pin(future::ready(FetchResult::Cancelled(CancelReason::Evicted))),
+        })
     }
 
-    /// Execute mutation on this [`QueryClient`]
-    ///
-    /// # Errors
-    /// Will error if the mutation function errors
-    ///
-    /// # Panics
-    /// Will always panic on engine-side
-    #[cfg(target_arch = "wasm32")]
-    pub async fn mutate<P, R, E, C>(
+    /// Fetch a query on this client with a borrowed `arg`, for when the query function only needs a reference to it
+    /// `arg` is only cloned if the fetch has to continue once the connection returns, see [`FetchResult::NoConnection`]
+    pub async fn fetch_with_ref<P: Clone, R, E: Error>(
         &self,
-        mutation: &Mutation<'link, P, R, E>,
-        value: P,
-        default_cb: Option<&MutationCallbacks<P, R, E, C>>,
-        cb: Option<MutationCallbacks<P, R, E, C>>,
-    ) -> Result<Rc<R>, MutateError<E>> {
-        // let id = mutation.inner.link.with_or_else(
-        //     &self.inner.mutation_cache.link_target,
-        //     || self.new_mutate_meta(mutation),
-        //     |e| e.id,
-        // );
+        query: &Query<'link, P, R, E>,
+        arg: &P,
+    ) -> FetchResult<R, E> {
+        if self.inner.resolved.no_cache(&query.inner.opts) {
+            return self.inner.fetch_uncached(&query.inner, arg).await;
+        }
 
-        /*use crate::mutation::MutateMeta;
+        let id = query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || self.new_fetch_meta(query),
+            |e| e.id,
+        );
 
-        let online = crate::browser::online_handler::is_online();
+        Rc::clone(&self.inner)
+            .fetch_with_arg_inner(
+                Rc::clone(&query.inner),
+                FetchArg::Borrowed(arg, P::clone),
+                id,
+                1,
+            )
+            .await
+    }
 
-        let cx = match default_cb {
-            Some(cb) => match cb.on_mutate {
-                Some(ref f) => f(&mut value).await,
-                None => None,
+    /// Subscribe to several queries on this client, which may each have different types, held by a single [`Guard`]
+    #[inline]
+    pub const fn subscribe_many(&self) -> SubscribeMany<'_, 'link> {
+        SubscribeMany {
+            client: self,
+            guards: Vec::new(),
+        }
+    }
+
+    /// Subscribe to changes of the data for the given `query` on this client
+    /// The entry is kept active, and so won't be removed from the cache, until the returned [`Guard`] is dropped
+    #[inline]
+    pub fn subscribe_query<P, R, E>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        f: impl Fn(QueryData<R, E>) + 'link,
+    ) -> Guard<'link> {
+        self.get_or_subscribe(query, f).1
+    }
+
+    /// Get the current data for the given `query` on this client, if it's cached, and subscribe to changes of it, see [`Self::subscribe_query`]
+    /// Both happen at once, so there's no gap between them where a change could be missed
+    pub fn get_or_subscribe<P, R, E>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        f: impl Fn(QueryData<R, E>) + 'link,
+    ) -> (Option<QueryData<R, E>>, Guard<'link>) {
+        let created = Cell::new(false);
+        let (data, entry_id, handle) = query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || {
+                created.set(true);
+                self.new_fetch_meta(query)
             },
-            None => match mutation
-                .link
-                .with_entry(&self.inner.link_target, |e| match e {
-                    Entry::Occupied(o) => {
-                        o.get().default_cb.on_mutate.as_ref().map(|f| f(&mut value))
-                    }
-                    Entry::Vacant(_) => None,
-                }) {
-                Some(f) => f.await,
-                None => None,
+            |e| {
+                e.cache_control.set_active(true);
+                if let Some(refetch) = query.inner.refetch_no_param.filter(|_| e.refetch.is_none())
+                {
+                    e.refetch = Some(refetch(query));
+                }
+                let data = (!created.get()).then(|| (*e.data).clone());
+                (data, e.entry_id, e.data.add_listener(f))
             },
-        };
+        );
 
-        let new_data = match online {
-            true => MutationData::Loading,
-            false => MutationData::Err(MutateError::NoConnection),
-        };
+        let cache = Rc::downgrade(&self.inner.query_cache);
+        let query = Rc::downgrade(&query.inner);
+        let guard = Guard::new(move || {
+            let (Some(cache), Some(query)) = (cache.upgrade(), query.upgrade()) else {
+                return;
+            };
 
-        mutation
-            .link
-            .with_entry(&self.inner.link_target, |e| match e {
-                Entry::Occupied(mut o) => {
-                    o.get_mut().data = new_data.clone();
+            query.link.with_entry(&cache.link_target, |e| match e {
+                // The entry may have been removed & replaced since subscribing, the handle isn't valid for a new entry
+                Entry::Occupied(mut o) if o.get().entry_id == entry_id => {
+                    let entry = o.get_mut();
+                    entry.data.remove_listener(handle);
+                    entry.sync_active();
                 }
-                Entry::Vacant(v) => {
-                    v.insert(MutateMeta {
-                        data: new_data.clone(),
-                        ..MutateMeta::default()
-                    });
+                Entry::Occupied(_) | Entry::Vacant => {}
+            });
+        });
+        (data, guard)
+    }
+
+    /// Fetch a query on this client, keeping its entry active, and so cached, until the returned [`Guard`] is dropped
+    /// For caching outside of anything that would subscribe to the query
+    pub async fn fetch_and_retain<P, R, E: Error>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+    ) -> (FetchResult<R, E>, Guard<'link>) {
+        let entry_id = query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || self.new_fetch_meta(query),
+            |e| {
+                e.retained += 1;
+                e.cache_control.set_active(true);
+                e.entry_id
+            },
+        );
+
+        let cache = Rc::downgrade(&self.inner.query_cache);
+        let inner = Rc::downgrade(&query.inner);
+        let guard = Guard::new(move || {
+            let (Some(cache), Some(query)) = (cache.upgrade(), inner.upgrade()) else {
+                return;
+            };
+
+            query.link.with_entry(&cache.link_target, |e| match e {
+                // A replacement entry was never retained by this guard
+                Entry::Occupied(mut o) if o.get().entry_id == entry_id => {
+                    let entry = o.get_mut();
+                    entry.retained -= 1;
+                    entry.sync_active();
                 }
+                Entry::Occupied(_) | Entry::Vacant => {}
             });
+        });
 
-        if let Some(l) = mutation.link.borrow(&self.inner.link_target) {
-            for listener in &l.listeners {
-                listener(new_data.clone());
-            }
-        }
+        (self.fetch_with_arg(query, arg).await, guard)
+    }
 
-        if !online {
-            return Err(MutateError::NoConnection);
+    /// Subscribe to changes of the data for the given `query` on this client, see [`Self::subscribe_query`]
+    /// `arg` is recorded so background refetches, such as from [`Self::invalidate_query`], re-run the query with it
+    pub fn subscribe_query_with_arg<P: Clone + 'link, R: 'link, E: Error + 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+        f: impl Fn(QueryData<R, E>) + 'link,
+    ) -> Guard<'link> {
+        let guard = self.subscribe_query(query, f);
+
+        let refetch = Self::refetch_fn(query, Rc::new(arg));
+        query
+            .inner
+            .link
+            .with_entry(&self.inner.query_cache.link_target, |e| match e {
+                Entry::Occupied(mut o) => o.get_mut().refetch = Some(refetch),
+                Entry::Vacant => {}
+            });
+
+        guard
+    }
+
+    /// Observe changes of the data for the given `query` on this client as a [`Stream`], see [`Self::subscribe_query`]
+    /// The subscription lasts until the stream is dropped
+    pub fn query_stream<P, R, E>(
+        &self,
+        query: &Query<'link, P, R, E>,
+    ) -> impl Stream<Item = QueryData<R, E>> + 'link
+    where
+        R: 'link,
+        E: 'link,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let guard = self.subscribe_query(query, move |data| {
+            // Only fails once the stream is dropped, which unsubscribes anyway
+            drop(tx.unbounded_send(data));
+        });
+        GuardedStream { rx, _guard: guard }
+    }
+
+    /// Subscribe to changes of the data for the given `query` on this client for as long as `target` is alive
+    /// `f` is given the upgraded `target` with each change
+    ///
+    /// There is no [`Guard`], the listener is removed the next time the data changes after `target` has been dropped
+    pub fn subscribe_query_weak<P, R, E, T: ?Sized + 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        target: Weak<T>,
+        f: impl Fn(&T, QueryData<R, E>) + 'link,
+    ) {
+        let alive = Weak::clone(&target);
+        query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || self.new_fetch_meta(query),
+            |e| {
+                e.cache_control.set_active(true);
+                e.data.add_listener_direct(Listener {
+                    f: Box::new(move |data| {
+                        if let Some(target) = target.upgrade() {
+                            f(&target, data);
+                        }
+                    }),
+                    drop_f: None,
+                    alive: Some(Box::new(move || alive.strong_count() != 0)),
+                });
+            },
+        );
+    }
+
+    fn new_mutate_meta<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+    ) -> MutateMeta<'link, P, R, E> {
+        let cache_time = self.inner.resolved.cache_time(&mutation.inner.opts);
+
+        MutateMeta {
+            data: Listenable::new(MutationData::default()),
+            id: atomic_id::next(),
+            entry_id: atomic_id::next(),
+            cache_control: CacheControl::new(
+                Rc::downgrade(&self.inner.mutation_cache),
+                Rc::downgrade(&mutation.inner),
+                cache_time,
+            ),
         }
+    }
 
-        let result = mutation.execute(&value).await;
+    /// Subscribe to changes of the data for the given `mutation` on this client
+    /// The entry is kept active, and so won't be removed from the cache, until the returned [`Guard`] is dropped
+    pub fn subscribe_mutation<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        f: impl Fn(MutationData<P, R, E>) + 'link,
+    ) -> Guard<'link> {
+        let (entry_id, handle) = mutation.inner.link.with_or_else(
+            &self.inner.mutation_cache.link_target,
+            || self.new_mutate_meta(mutation),
+            |e| {
+                e.cache_control.set_active(true);
+                (e.entry_id, e.data.add_listener(f))
+            },
+        );
 
-        let (result, ret) = match result {
-            Ok(r) => {
-                let r = Rc::new(r);
-                (MutationData::Ok(Rc::clone(&r)), Ok(r))
-            }
-            Err(e) => {
-                let e = Rc::new(e);
-                (
-                    MutationData::Err(MutateError::FnError(Rc::clone(&e))),
-                    Err(MutateError::FnError(e)),
-                )
-            }
-        };
+        let cache = Rc::downgrade(&self.inner.mutation_cache);
+        let mutation = Rc::downgrade(&mutation.inner);
+        Guard::new(move || {
+            let (Some(cache), Some(mutation)) = (cache.upgrade(), mutation.upgrade()) else {
+                return;
+            };
+
+            mutation.link.with_entry(&cache.link_target, |e| match e {
+                // The entry may have been removed & replaced since subscribing, the handle isn't valid for a new entry
+                Entry::Occupied(mut o) if o.get().entry_id == entry_id => {
+                    let entry = o.get_mut();
+                    if entry.data.remove_listener(handle) == 0 {
+                        entry.cache_control.set_active(false);
+                    }
+                }
+                Entry::Occupied(_) | Entry::Vacant => {}
+            });
+        })
+    }
 
+    /// Observe changes of the data for the given `mutation` on this client as a [`Stream`], see [`Self::subscribe_mutation`]
+    /// The subscription lasts until the stream is dropped
+    pub fn mutation_stream<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+    ) -> impl Stream<Item = MutationData<P, R, E>> + 'link
+    where
+        R: 'link,
+        E: 'link,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let guard = self.subscribe_mutation(mutation, move |data| {
+            // Only fails once the stream is dropped, which unsubscribes anyway
+            drop(tx.unbounded_send(data));
+        });
+        GuardedStream { rx, _guard: guard }
+    }
+
+    /// Sets the data for `mutation` if `id` is still the latest mutation to be initiated
+    fn set_mutation_data<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        id: usize,
+        data: MutationData<P, R, E>,
+    ) {
         mutation
+            .inner
             .link
-            .with_entry(&self.inner.link_target, |e| match e {
-                Entry::Occupied(mut o) => {
-                    o.get_mut().data = result.clone();
-                }
-                Entry::Vacant(v) => {
-                    v.insert(MutateMeta {
-                        data: result.clone(),
-                        ..MutateMeta::default()
-                    });
+            .with_entry(&self.inner.mutation_cache.link_target, |e| match e {
+                Entry::Occupied(mut o) if o.get().id == id => {
+                    Listenable::set(&mut o.get_mut().data, data);
                 }
+                Entry::Occupied(_) | Entry::Vacant => {}
             });
+    }
+
+    /// Execute mutation on this [`QueryClient`]
+    ///
+    /// Both `default_cb` and `cb` run for each stage of the mutation, `default_cb` first
+    /// Each gets its own context, created by its own `on_mutate` callback, or `None` if it doesn't have one
+    ///
+    /// # Errors
+    /// Will error if the mutation function errors, or if there is no internet connection
+    pub async fn mutate<P, R, E, C>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        value: P,
+        default_cb: Option<&MutationCallbacks<P, R, E, C>>,
+        cb: Option<MutationCallbacks<P, R, E, C>>,
+    ) -> Result<Rc<R>, MutateError<E>> {
+        Self::in_mutate_span(mutation, self.mutate_inner(mutation, value, default_cb, cb)).await
+    }
+
+    /// Runs `mutate` within a span for `mutation`, when tracing
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    async fn in_mutate_span<P, R, E>(
+        mutation: &Mutation<'link, P, R, E>,
+        mutate: impl Future<Output = Result<Rc<R>, MutateError<E>>>,
+    ) -> Result<Rc<R>, MutateError<E>> {
+        #[cfg(feature = "tracing")]
+        let mutate = crate::trace::in_span(
+            tracing::info_span!(
+                "mutate",
+                mutation = mutation.id(),
+                outcome = tracing::field::Empty,
+            ),
+            mutate,
+            crate::trace::mutate_outcome,
+        );
+        mutate.await
+    }
+
+    async fn mutate_inner<P, R, E, C>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        mut value: P,
+        default_cb: Option<&MutationCallbacks<P, R, E, C>>,
+        cb: Option<MutationCallbacks<P, R, E, C>>,
+    ) -> Result<Rc<R>, MutateError<E>> {
+        let id = self.start_mutation(mutation);
 
-        for cb in [default_cb.map(|cb| &cb.inner), cb.as_ref()]
-            .into_iter()
-            .flatten()
-        {
-            let settled_ret = match ret {
+        let callbacks: Vec<_> = [default_cb, cb.as_ref()].into_iter().flatten().collect();
+        let mut contexts = Vec::with_capacity(callbacks.len());
+        for cb in &callbacks {
+            contexts.push(match cb.on_mutate {
+                Some(ref f) => f(&mut value).await,
+                None => None,
+            });
+        }
+
+        self.run_mutation(mutation, id, value, &callbacks, &contexts)
+            .await
+    }
+
+    /// Makes a new mutation of `mutation` the latest to be initiated, returning its id
+    fn start_mutation<P, R, E>(&self, mutation: &Mutation<'link, P, R, E>) -> usize {
+        mutation.inner.link.with_or_else(
+            &self.inner.mutation_cache.link_target,
+            || self.new_mutate_meta(mutation),
+            |e| {
+                e.id = atomic_id::next();
+                e.id
+            },
+        )
+    }
+
+    /// Runs the mutation started with `id`, then each of `callbacks` with its context from `contexts`
+    async fn run_mutation<P, R, E, C>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        id: usize,
+        value: P,
+        callbacks: &[&MutationCallbacks<P, R, E, C>],
+        contexts: &[Option<C>],
+    ) -> Result<Rc<R>, MutateError<E>> {
+        // Only shared once `on_mutate` callbacks have had the chance to change it
+        let value = Rc::new(value);
+        let result = self.execute_mutation(mutation, id, &value).await;
+
+        for (cb, cx) in callbacks.iter().zip(contexts) {
+            let settled = match result {
                 Ok(ref r) => {
                     if let Some(ref f) = cb.on_success {
-                        f(Rc::clone(r), &value, &cx).await;
+                        f(Rc::clone(r), &value, cx).await;
                     }
                     Ok(Rc::clone(r))
                 }
                 Err(MutateError::FnError(ref e)) => {
                     if let Some(ref f) = cb.on_error {
-                        f(Rc::clone(e), &value, &cx).await;
+                        f(Rc::clone(e), &value, cx).await;
                     }
                     Err(Rc::clone(e))
                 }
-                // SAFETY: `ret` never constructed with an error case other than MutateError::FnError
-                Err(_) => unsafe {
-                    std::hint::unreachable_unchecked();
-                },
+                Err(MutateError::NoConnection) => {
+                    if let Some(ref f) = cb.on_no_connection {
+                        f(&value, cx).await;
+                    }
+                    continue;
+                }
             };
 
             if let Some(ref f) = cb.on_settled {
-                f(settled_ret, &value, &cx).await;
+                f(settled, &value, cx).await;
             }
         }
 
-        if let Some(l) = mutation.link.borrow(&self.inner.link_target) {
-            for listener in &l.listeners {
-                listener(result.clone());
-            }
+        let data = match result {
+            Ok(ref r) => MutationData::Ok(Rc::clone(r)),
+            Err(ref e) => MutationData::Err(e.clone()),
+        };
+        self.set_mutation_data(mutation, id, data);
+        result
+    }
+
+    /// Runs `mutation` with `value` if there's an internet connection, marking it as loading while it runs
+    async fn execute_mutation<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        id: usize,
+        value: &Rc<P>,
+    ) -> Result<Rc<R>, MutateError<E>> {
+        #[cfg(target_arch = "wasm32")]
+        if !crate::browser::online_handler::is_online() {
+            return Err(MutateError::NoConnection);
         }
-        ret*/
-        todo!()
+
+        self.set_mutation_data(mutation, id, MutationData::Loading(Some(Rc::clone(value))));
+        mutation
+            .execute(value)
+            .await
+            .map(Rc::new)
+            .map_err(|e| MutateError::FnError(Rc::new(e)))
+    }
+
+    /// Execute mutation on this [`QueryClient`] in the background, without waiting for the result, see [`Self::mutate`]
+    /// The result can be observed with [`Self::subscribe_mutation`], the mutation is aborted if the returned [`Guard`] is dropped before it completes
+    /// Like [`Self::prefetch`], it's also aborted once every clone of this client is dropped
+    pub fn mutate_spawn<P, R, E, C>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        value: P,
+        cb: Option<MutationCallbacks<P, R, E, C>>,
+    ) -> Guard<'link>
+    where
+        P: 'link,
+        R: 'link,
+        E: 'link,
+        C: 'link,
+    {
+        let inner = Rc::clone(&self.inner);
+        let mutation = Mutation {
+            inner: Rc::clone(&mutation.inner),
+        };
+        let cleanup = self.inner.spawn(async move {
+            drop(
+                Self::detached(inner)
+                    .mutate(&mutation, value, None, cb)
+                    .await,
+            );
+        });
+        let abort = cleanup.map(|c| c.abort_on_drop());
+        Guard::new(move || drop(abort))
+    }
+
+    /// Execute mutation on this [`QueryClient`], optimistically setting the data of `target_query` to `optimistic` while it runs
+    ///
+    /// If the mutation errors, or can't run without an internet connection, the previous data of `target_query` is restored
+    ///
+    /// `replace` sets what happens to the optimistic data if the mutation succeeds:
+    /// - `true`: it's replaced with the result of the mutation
+    /// - `false`: it's kept, such as when the query will be refetched anyway
+    ///
+    /// # Errors
+    /// Will error if the mutation function errors, or if there is no internet connection
+    pub async fn mutate_optimistic<P, R: 'link, E, QP: 'link, QE: 'link>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+        value: P,
+        target_query: &Query<'link, QP, R, QE>,
+        optimistic: R,
+        replace: bool,
+    ) -> Result<Rc<R>, MutateError<E>> {
+        let mutate = async {
+            let id = self.start_mutation(mutation);
+            let context = Optimistic {
+                client: self.clone(),
+                query: target_query.clone(),
+                snapshot: self.query_data(target_query),
+            };
+            self.set_query_data(target_query, optimistic);
+
+            let callbacks = MutationCallbacks::new()
+                .on_success(move |r, _, cx: &Option<Optimistic<'_, QP, R, QE>>| {
+                    match *cx {
+                        Some(ref cx) if replace => cx
+                            .client
+                            .set_query_state(&cx.query, QueryData::Ok(r, QueryStatus::Idle)),
+                        Some(_) | None => {}
+                    }
+                    Box::pin(async {})
+                })
+                .on_error(|_, _, cx: &Option<Optimistic<'_, QP, R, QE>>| {
+                    if let Some(ref cx) = *cx {
+                        cx.rollback();
+                    }
+                    Box::pin(async {})
+                })
+                .on_no_connection(|_, cx: &Option<Optimistic<'_, QP, R, QE>>| {
+                    if let Some(ref cx) = *cx {
+                        cx.rollback();
+                    }
+                    Box::pin(async {})
+                });
+            self.run_mutation(mutation, id, value, &[&callbacks], &[Some(context)])
+                .await
+        };
+        Self::in_mutate_span(mutation, mutate).await
+    }
+
+    /// Set the data for `query` on this client, notifying subscribers
+    /// Creates the cache entry if it doesn't exist
+    /// If called while the query's data is being set, such as by a subscriber being notified of it, the data is set once that's done
+    pub fn set_query_data<P: 'link, R: 'link, E: 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        data: R,
+    ) {
+        self.set_query_state(query, QueryData::Ok(Rc::new(data), QueryStatus::Idle));
+    }
+
+    /// Set an error for `query` on this client, notifying subscribers, as if its query function had returned `err`
+    /// Creates the cache entry if it doesn't exist
+    /// If called while the query's data is being set, such as by a subscriber being notified of it, the error is set once that's done
+    pub fn set_query_error<P: 'link, R: 'link, E: 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        err: E,
+    ) {
+        self.set_query_state(query, QueryData::Err(Rc::new(err), QueryStatus::Idle));
+    }
+
+    fn set_query_state<P: 'link, R: 'link, E: 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        data: QueryData<R, E>,
+    ) {
+        let default = {
+            let client = self.clone();
+            let query = query.clone();
+            move || client.new_fetch_meta(&query)
+        };
+        query
+            .inner
+            .link
+            .with_or_else_deferred(&self.inner.query_cache.link_target, default, |e| {
+                e.set_data(data);
+            });
     }
 
     /// Get an owned copy of the the data in the client cache for the given ``query``
@@ -420,180 +1468,761 @@ impl<'link> QueryClient<'link> {
         self.inner.query_cache.data(query)
     }
 
+    /// Get an owned copy of the the data in the client cache for the given ``mutation``
+    #[must_use = "Has no effect other than to clone the data into an ownable type, which you should use"]
+    pub fn mutation_data<P, R, E>(
+        &self,
+        mutation: &Mutation<'link, P, R, E>,
+    ) -> Option<MutationData<P, R, E>> {
+        self.inner.mutation_cache.data(mutation)
+    }
+
+    /// Get the current state of the given `query` in the client cache, without fetching
+    #[must_use = "Has no effect other than to clone the state into an ownable type, which you should use"]
+    pub fn peek<P, R, E>(&self, query: &Query<'link, P, R, E>) -> QueryPeek<R, E> {
+        query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .map_or(
+                QueryPeek {
+                    data: None,
+                    is_fetching: false,
+                    observer_count: 0,
+                    data_updated_at: None,
+                    error_updated_at: None,
+                },
+                |e| QueryPeek {
+                    data: Some(e.data.clone()),
+                    is_fetching: e.data.is_fetching(),
+                    observer_count: e.data.listener_count(),
+                    data_updated_at: e.data_updated_at,
+                    error_updated_at: e.error_updated_at,
+                },
+            )
+    }
+
+    /// Get an owned copy of the fetch metrics in the client cache for the given `query`
+    #[must_use = "Has no effect other than to clone the metrics into an ownable type, which you should use"]
+    pub fn query_metrics<P, R, E>(&self, query: &Query<'link, P, R, E>) -> Option<FetchMetrics> {
+        query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .map(|e| e.metrics.clone())
+    }
+
+    /// Fetch a query on this client, blocking until it's done, for tests that aren't async
+    /// Any tasks it spawns, such as for cache eviction, carry on in the next call on this thread, see [`test_harness::block_on`]
+    #[cfg(all(test, not(target_arch = "wasm32")))]
+    pub(crate) fn fetch_blocking<P, R, E: Error>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+    ) -> FetchResult<R, E> {
+        crate::test_harness::block_on(self.fetch_with_arg(query, arg))
+    }
+
     /// Fetch a query that takes no argument on this client
+    /// Joins a fetch of `query` already in progress on any client sharing this client's [`QueryCache`], rather than running it again
     #[inline]
     pub async fn fetch<R, E: Error>(&self, query: &Query<'link, (), R, E>) -> FetchResult<R, E> {
-        self.fetch_with_arg(query, ()).await
+        self.fetch_or_join(query, Rc::new(())).await
+    }
+
+    /// Joins the current fetch of `query` if there is one, otherwise fetches it for others to join
+    /// Only for queries that are always fetched with the same `arg`, so a fetch with a different one is never joined
+    async fn fetch_or_join<P, R, E: Error>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: Rc<P>,
+    ) -> FetchResult<R, E> {
+        if self.inner.resolved.no_cache(&query.inner.opts) {
+            return self.inner.fetch_uncached(&query.inner, &arg).await;
+        }
+
+        let shared = query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .and_then(|e| {
+                e.shared_fetch
+                    .upgrade()
+                    .filter(|s| s.id == e.id && s.pending())
+            });
+        if let Some(shared) = shared {
+            match shared.join().await {
+                Joined::Fresh(result) => return FetchResult::Fresh(result),
+                Joined::Cancelled(reason) => return FetchResult::Cancelled(reason),
+                Joined::Rerun => {}
+            }
+        }
+
+        let lead = query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || self.new_fetch_meta(query),
+            |e| {
+                let lead = LeadFetch(Rc::new(SharedFetch::new(e.id)));
+                e.shared_fetch = Rc::downgrade(&lead.0);
+                e.refetch = Some(Self::refetch_fn(query, Rc::clone(&arg)));
+                lead
+            },
+        );
+        let result = Rc::clone(&self.inner)
+            .fetch_with_arg_inner(Rc::clone(&query.inner), FetchArg::Shared(arg), lead.0.id, 1)
+            .await;
+        lead.0.finish(&result);
+        result
+    }
+
+    /// Fetch the data for `key` of a [`KeyedQuery`] on this client
+    #[inline]
+    pub async fn fetch_keyed<K: QueryKey, R: 'link, E: Error + 'link>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+        key: K,
+    ) -> FetchResult<R, E> {
+        self.register_keyed(query);
+        self.fetch_or_join(&query.query(&key), Rc::new(key)).await
+    }
+
+    /// Subscribe to changes of the data for `key` of a [`KeyedQuery`] on this client, see [`Self::subscribe_query`]
+    #[inline]
+    pub fn subscribe_keyed<K: QueryKey, R: 'link, E: Error + 'link>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+        key: &K,
+        f: impl Fn(QueryData<R, E>) + 'link,
+    ) -> Guard<'link> {
+        self.register_keyed(query);
+        self.subscribe_query(&query.query(key), f)
+    }
+
+    /// Get an owned copy of the data in the client cache for `key` of a [`KeyedQuery`]
+    #[must_use = "Has no effect other than to clone the data into an ownable type, which you should use"]
+    pub fn keyed_query_data<K: QueryKey, R, E: Error>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+        key: &K,
+    ) -> Option<QueryData<R, E>> {
+        self.query_data(&query.query(key))
+    }
+
+    /// Set the data for `key` of a [`KeyedQuery`] on this client, see [`Self::set_query_data`]
+    /// For warming its cache with data already fetched by another query, such as the items of a list seeding the query for each item's details,
+    /// so it can be shown straight away. Each key seeded is registered like any other, so [`Self::invalidate_where`] & [`Self::invalidate_keyed`] still find it
+    pub fn seed_query<K: QueryKey, R: 'link, E: Error + 'link>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+        key: &K,
+        data: R,
+    ) {
+        self.register_keyed(query);
+        self.set_query_data(&query.query(key), data);
+    }
+
+    /// Invalidate the data for `key` of a [`KeyedQuery`] on this client
+    /// If it's active, it's refetched in the background, see [`Self::prefetch_with_arg`]
+    pub fn invalidate_keyed<K: QueryKey, R: 'link, E: Error + 'link>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+        key: &K,
+    ) {
+        self.invalidate_with_arg(&query.query(key), key.clone());
+    }
+
+    /// Invalidate the data for every key of each [`KeyedQuery`] used with this client that matches `predicate`, see [`Self::invalidate_keyed`]
+    pub fn invalidate_where(&self, predicate: impl Fn(&QueryDescriptor<'_>) -> bool) {
+        let keyed: Vec<_> = {
+            let mut keyed = self.inner.keyed.borrow_mut();
+            keyed.retain(|k| k.strong_count() != 0);
+            keyed.iter().filter_map(|k| k.upgrade()).collect()
+        };
+
+        for query in keyed {
+            query.invalidate_where(self, &predicate);
+        }
+    }
+
+    /// Clear the error of every errored query on this client, such as after an outage
+    /// Active ones are reset to loading & refetched with the arg they were last fetched or subscribed with, see [`Self::invalidate_query`],
+    /// or keep their error if there isn't one, such as when the error was set directly. Inactive ones are removed from the cache
+    pub fn clear_errors(&self) {
+        let queries: Vec<_> = {
+            let mut queries = self.inner.queries.borrow_mut();
+            queries.retain(|q| q.strong_count() != 0);
+            queries.iter().filter_map(|q| q.upgrade()).collect()
+        };
+
+        for query in queries {
+            query.clear_error(self);
+        }
+    }
+
+    /// Ids of every active entry in this client's cache, for diagnostics
+    /// An entry's id identifies it for as long as it stays in the cache
+    #[must_use = "Has no effect other than to get the ids"]
+    pub fn active_query_ids(&self) -> Vec<usize> {
+        let queries: Vec<_> = {
+            let mut queries = self.inner.queries.borrow_mut();
+            queries.retain(|q| q.strong_count() != 0);
+            queries.iter().filter_map(|q| q.upgrade()).collect()
+        };
+
+        queries.iter().filter_map(|q| q.active_id(self)).collect()
+    }
+
+    /// Serialized data of every query made with [`Query::persistable`] that has data on this client, by its key
+    /// Queries that errored, are still loading or whose serializer returned `None` are left out
+    #[must_use = "Has no effect other than to serialize the data"]
+    pub fn persist_all(&self) -> HashMap<String, String> {
+        let queries: Vec<_> = {
+            let mut queries = self.inner.queries.borrow_mut();
+            queries.retain(|q| q.strong_count() != 0);
+            queries.iter().filter_map(|q| q.upgrade()).collect()
+        };
+
+        queries.iter().filter_map(|q| q.persist(self)).collect()
+    }
+
+    fn register_keyed<K: QueryKey, R: 'link, E: Error + 'link>(
+        &self,
+        query: &KeyedQuery<'link, K, R, E>,
+    ) {
+        // Non-trivial
+        #[allow(trivial_casts)]
+        self.inner.keyed.borrow_mut().insert(HashWeakPtr(
+            Rc::downgrade(&query.inner) as Weak<dyn InvalidateKeyed<'link> + 'link>
+        ));
+    }
+
+    fn invalidate_with_arg<P: 'link, R: 'link, E: Error + 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+    ) {
+        let active = query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .is_some_and(|e| e.cache_control.active());
+        if active {
+            self.prefetch_with_arg(query, arg);
+        }
+    }
+
+    /// Invalidate the data for the given `query` on this client
+    /// If it's active, it's refetched in the background with the arg it was last fetched or subscribed with, see [`Self::subscribe_query_with_arg`]
+    pub fn invalidate_query<P, R, E>(&self, query: &Query<'link, P, R, E>) {
+        let refetch = query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .filter(|e| e.cache_control.active())
+            .and_then(|e| e.refetch.clone());
+        if let Some(refetch) = refetch {
+            self.spawn_fetch(refetch(self));
+        }
+    }
+
+    /// Invalidate the data for the given `query` on this client, waiting for it to be refetched, see [`Self::invalidate_query`]
+    /// Unlike [`Self::invalidate_query`] it's refetched even if it isn't active, as the fresh data is being waited for
+    ///
+    /// Returns `None` without fetching if a query that takes an arg hasn't been fetched or subscribed with one on this client,
+    /// so there's no arg to refetch it with
+    pub async fn invalidate_and_await<P, R, E>(
+        &self,
+        query: &Query<'link, P, R, E>,
+    ) -> Option<FetchResult<R, E>> {
+        let refetch = query
+            .inner
+            .link
+            .borrow(&self.inner.query_cache.link_target)
+            .and_then(|e| e.refetch.clone())
+            .or_else(|| query.inner.refetch_no_param.map(|refetch| refetch(query)))?;
+        Some(refetch(self).await)
+    }
+
+    /// Fetch a query on this client, unless `cancel` completes first
+    /// If cancelled, the fetch is dropped & the entry in the cache is no longer marked as loading, see [`PendingStatus::Idle`]
+    /// A [`CancelToken`] can cancel several fetches at once by passing [`CancelToken::cancelled`] to each
+    pub async fn fetch_with_cancel<P, R, E: Error, F: Future>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+        cancel: F,
+    ) -> FetchResult<R, E> {
+        select! {
+            res = self.fetch_with_arg(query, arg) => res,
+            _ = cancel => {
+                self.settle_cancelled(query);
+                FetchResult::Cancelled(CancelReason::Explicit)
+            }
+        }
+    }
+
+    fn settle_cancelled<P, R, E>(&self, query: &Query<'link, P, R, E>) {
+        query
+            .inner
+            .link
+            .with_entry(&self.inner.query_cache.link_target, |e| match e {
+                Entry::Occupied(mut o) => o.get_mut().settle_cancelled(),
+                Entry::Vacant => {}
+            });
+    }
+
+    /// Wait until no fetches are in progress on this client, such as before reading the cache once it's populated
+    /// Fetches waiting out a retry delay are still in progress, but ones waiting for a connection aren't until it returns
+    pub async fn wait_for_idle(&self) {
+        loop {
+            // Created before checking, so a fetch finishing in between can't be missed
+            let idle = self.inner.idle.notified();
+            if self.inner.in_flight.get() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    /// Cancel every fetch in progress on this client, such as when tearing down a large screen
+    /// Fetches being awaited return [`FetchResult::Cancelled`] with [`CancelReason::Explicit`], background fetches are aborted,
+    /// and entries are no longer marked as loading, see [`PendingStatus::Idle`]. Subscriptions are kept, only their fetches stop
+    pub fn cancel_all(&self) {
+        let queries: Vec<_> = {
+            let mut queries = self.inner.queries.borrow_mut();
+            queries.retain(|q| q.strong_count() != 0);
+            queries.iter().filter_map(|q| q.upgrade()).collect()
+        };
+        for query in queries {
+            query.cancel(self);
+        }
+
+        // Taken out first, as aborting runs cleanup that removes each handle from the map
+        let handles = self.spawned.handles.borrow_mut().take();
+        drop(handles);
+        self.inner.cancel_all.notify_waiters();
+    }
+
+    /// Fetch a query in the background on this client, without waiting for the result
+    /// The fetch is aborted if every clone of this client is dropped before it completes
+    /// If the query takes no param, it joins a fetch already in progress like [`Self::fetch`]
+    pub fn prefetch_with_arg<P: 'link, R: 'link, E: Error + 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: P,
+    ) {
+        if query.inner.takes_no_param() {
+            let inner = Rc::clone(&self.inner);
+            let query = query.clone();
+            self.spawn_fetch(Box::pin(async move {
+                Self::detached(inner)
+                    .fetch_or_join(&query, Rc::new(arg))
+                    .await
+            }));
+            return;
+        }
+
+        self.spawn_fetch(self.start_fetch(query, Rc::new(arg)));
+    }
+
+    /// Creates the entry for `query` if needed, returning the fetch for it
+    /// Holds the inner client rather than a clone, so spawning it doesn't keep the client's spawned futures alive
+    fn start_fetch<P: 'link, R: 'link, E: Error + 'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        arg: Rc<P>,
+    ) -> FetchFuture<'link, R, E> {
+        let inner = Rc::clone(&self.inner);
+        if inner.resolved.no_cache(&query.inner.opts) {
+            let query = Rc::clone(&query.inner);
+            return Box::pin(async move { inner.fetch_uncached(&query, &arg).await });
+        }
+
+        let id = query.inner.link.with_or_else(
+            &self.inner.query_cache.link_target,
+            || self.new_fetch_meta(query),
+            |e| {
+                e.refetch = Some(Self::refetch_fn(query, Rc::clone(&arg)));
+                e.id
+            },
+        );
+
+        let query = Rc::clone(&query.inner);
+        Box::pin(async move {
+            inner
+                .fetch_with_arg_inner(query, FetchArg::Shared(arg), id, 1)
+                .await
+        })
+    }
+
+    fn spawn_fetch<R: 'link, E: 'link>(&self, fetch: FetchFuture<'link, R, E>) {
+        self.inner.spawn(async move {
+            fetch.await;
+        });
+    }
+
+    /// Fetch a query that takes no argument in the background on this client, without waiting for the result
+    /// The fetch is aborted if every clone of this client is dropped before it completes
+    #[inline]
+    pub fn prefetch<R: 'link, E: Error + 'link>(&self, query: &Query<'link, (), R, E>) {
+        self.prefetch_with_arg(query, ());
+    }
+
+    /// Collect fetches of several queries on this client, which may each have different types, to await them all together
+    #[inline]
+    pub fn prefetch_all(&self) -> PrefetchAll<'link> {
+        PrefetchAll {
+            client: self.clone(),
+            fetches: Vec::new(),
+        }
     }
 }
 
 impl<'link> QueryClientInner<'link> {
-    fn fetch_with_arg_inner<P, R, E: Error>(
+    /// Marks `query` as used for [`ClientOpts::max_cached_queries`], not tracked unless there is a limit
+    fn touch_query<P, R, E>(&self, query: &Rc<QueryInner<'link, P, R, E>>) {
+        if self.opts.max_cached_queries.is_some() {
+            self.query_cache.touch(query);
+        }
+    }
+
+    /// Ends the batch started when syncing the status of `query`, see [`FetchMeta::start_batch`]
+    fn end_batch<P, R, E>(&self, query: &QueryInner<'link, P, R, E>) {
+        query
+            .link
+            .with_entry(&self.query_cache.link_target, |e| match e {
+                Entry::Occupied(mut o) => o.get_mut().end_batch(),
+                Entry::Vacant => {}
+            });
+    }
+
+    /// Spawn a future that will be aborted once every clone of the client is dropped
+    /// Returns `None` without spawning if that has already happened
+    fn spawn(&self, f: impl Future<Output = ()> + 'link) -> Option<CleanupHandle<'link>> {
+        let spawned = self.spawned.upgrade()?;
+        let handle = future_handle::spawn_local_handle(f);
+        let ret = handle.cleanup();
+        let map_handle = Rc::new(Cell::new(Some(spawned.handles.borrow_mut().insert(handle))));
+
+        let spawned = Rc::downgrade(&spawned);
+        let remove = move || {
+            if let Some(spawned) = spawned.upgrade() {
+                if let Some(map_handle) = map_handle.take() {
+                    // Dropped once the map is released, as dropping it runs cleanup
+                    let handle = spawned.handles.borrow_mut().remove(map_handle);
+                    drop(handle);
+                }
+            }
+        };
+        ret.add_cleanup_on(CleanupOn::Abort, remove.clone());
+        ret.add_cleanup(remove);
+
+        Some(ret)
+    }
+
+    /// Runs `query` without an entry in the cache, retrying as configured, see [`QueryOpts.no_cache`]
+    /// Nothing is stored or notified, so the connection isn't waited for & the result is always [`FetchResult::Fresh`] unless cancelled
+    async fn fetch_uncached<P, R, E: Error>(
+        &self,
+        query: &QueryInner<'link, P, R, E>,
+        arg: &P,
+    ) -> FetchResult<R, E> {
+        let _in_flight = InFlight::start(self);
+        let started = Instant::now();
+        let retry_config = resolve::resolve_retry(&self.opts, &query.opts);
+        let mut count = 1_u32;
+        loop {
+            let result = select! {
+                result = query.execute_with_arg(arg) => result,
+                () = self.cancel_all.notified() => {
+                    return FetchResult::Cancelled(CancelReason::Explicit);
+                }
+            };
+            let error = match result {
+                Ok(r) => return FetchResult::Fresh(Ok(Rc::new(r))),
+                Err(e) => Rc::new(e),
+            };
+            let Some(retry) = retry_config.retry_delay(count, Rc::clone(&error), started.elapsed())
+            else {
+                return FetchResult::Fresh(Err(error));
+            };
+            retry_config.notify_retry(count, error);
+
+            select! {
+                () = sleep::sleep(retry) => {}
+                () = self.cancel_all.notified() => {
+                    return FetchResult::Cancelled(CancelReason::Explicit);
+                }
+            }
+            count = count.checked_add(1).expect("retry count overflowed");
+        }
+    }
+
+    fn fetch_with_arg_inner<'arg, P, R, E: Error>(
         self: Rc<Self>,
-        query: Rc<crate::query::QueryInner<'link, P, R, E>>,
-        arg: P,
+        query: Rc<QueryInner<'link, P, R, E>>,
+        arg: FetchArg<'arg, P>,
         id: usize,
-        count: u32,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = FetchResult<R, E>> + '_>> {
-        enum Retry<T> {
-            Retry(Duration),
+        mut count: u32,
+    ) -> Pin<Box<dyn Future<Output = FetchResult<R, E>> + 'arg>>
+    where
+        'link: 'arg,
+    {
+        enum Retry<T, E> {
+            Retry(Duration, Rc<E>),
             Return(T),
         }
 
-        Box::pin(async move {
-            #[cfg(target_arch = "wasm32")]
-            let online = crate::browser::online_handler::is_online();
-            #[cfg(target_arch = "wasm32")]
-            let new_status = PendingStatus::from_online(online);
-            #[cfg(not(target_arch = "wasm32"))]
-            let new_status = PendingStatus::Loading;
-
-            if query
-                .link
-                .with_entry(&self.query_cache.link_target, |e| match e {
-                    Entry::Occupied(o) if o.get().id != id => true,
-                    Entry::Vacant => true,
-                    Entry::Occupied(mut o) => {
-                        let entry = o.get_mut();
-                        match *entry.data {
-                            QueryData::Pending(ref s) if *s != new_status => {
-                                Listenable::set(&mut entry.data, QueryData::Pending(new_status));
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "fetch",
+            query = query.id(),
+            attempt = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let attempts = span.clone();
+        let fetch = async move {
+            let _in_flight = InFlight::start(&self);
+            if matches!(self.opts.yield_after_fetches, Some(max) if self.in_flight.get() > max) {
+                yield_now().await;
+            }
+            self.touch_query(&query);
+            let started = Instant::now();
+            let network_mode = self.resolved.network_mode(&query.opts);
+            let cache_errors = self.resolved.cache_errors(&query.opts);
+            // Retry within the same future, rather than boxing a new one for each attempt
+            loop {
+                // Connection doesn't matter for `Always`, so don't check it
+                #[cfg(target_arch = "wasm32")]
+                let online = matches!(network_mode, NetworkMode::Always)
+                    || crate::browser::online_handler::handler().online();
+                #[cfg(target_arch = "wasm32")]
+                let new_status = PendingStatus::from_online(online);
+                #[cfg(not(target_arch = "wasm32"))]
+                let new_status = PendingStatus::Loading;
+                #[cfg(feature = "tracing")]
+                attempts.record("attempt", count);
+
+                if let Some(reason) =
+                    query
+                        .link
+                        .with_entry(&self.query_cache.link_target, |e| match e {
+                            Entry::Occupied(o) if o.get().id != id => {
+                                Some(CancelReason::Superseded)
                             }
-                            QueryData::Ok(_, ref s) | QueryData::Err(_, ref s)
-                                if *s != new_status.as_query() =>
-                            {
-                                Listenable::modify(&mut entry.data, |d| match *d {
-                                    QueryData::Ok(_, ref mut s) | QueryData::Err(_, ref mut s) => {
-                                        *s = new_status.as_query();
+                            Entry::Vacant => Some(CancelReason::Evicted),
+                            Entry::Occupied(mut o) => {
+                                let entry = o.get_mut();
+                                // Ended once the query function has been polled, so a result that's ready straight away is notified along with the status
+                                entry.start_batch();
+                                match *entry.data {
+                                    QueryData::Pending(ref s) if *s != new_status => {
+                                        entry.set_data(QueryData::Pending(new_status));
                                     }
-                                    QueryData::Pending(_) => unreachable!(),
-                                });
-                            }
-                            _ => {}
-                        }
-
-                        false
-                    }
-                })
-            {
-                return FetchResult::Cancelled;
-            }
+                                    QueryData::Ok(_, ref s) | QueryData::Err(_, ref s)
+                                        if *s != new_status.as_query() =>
+                                    {
+                                        entry.modify_data(|d| match *d {
+                                            QueryData::Ok(_, ref mut s)
+                                            | QueryData::Err(_, ref mut s) => {
+                                                *s = new_status.as_query();
+                                            }
+                                            QueryData::Pending(_) => unreachable!(),
+                                        });
+                                    }
+                                    _ => {}
+                                }
 
-            let network_mode: NetworkMode =
-                resolve::resolve_option(ConfigOption::NetworkMode, &self.opts, &query.opts);
+                                None
+                            }
+                        })
+                {
+                    return FetchResult::Cancelled(reason);
+                }
 
-            #[cfg(target_arch = "wasm32")]
-            if !online && !network_mode.should_try(count) {
-                use crate::browser::online_handler::OnlineHandler;
+                #[cfg(target_arch = "wasm32")]
+                if !online && !network_mode.should_try(count) {
+                    use crate::browser::online_handler::OnlineHandler;
 
-                let no_conn = Rc::new(NoConnectionInner {
-                    result: RefCell::new(None),
-                    notify: Notify::new(),
-                });
+                    self.end_batch(&query);
+                    let no_conn = Rc::new(NoConnectionInner {
+                        result: RefCell::new(None),
+                        notify: Notify::new(),
+                    });
 
-                let handle = future_handle::spawn_local_handle({
-                    let this = Rc::clone(&self);
-                    let query = Rc::clone(&query);
-                    let no_conn = Rc::clone(&no_conn);
-                    async move {
-                        OnlineHandler::wait().await;
-
-                        let result = match this.fetch_with_arg_inner(query, arg, id, count).await {
-                            FetchResult::NoConnection(nc) => nc.wait().await,
-                            FetchResult::Fresh(f) => FetchResultWaited::Fresh(f),
-                            FetchResult::Stale(s) => FetchResultWaited::Stale(s),
-                            FetchResult::Cancelled => FetchResultWaited::Cancelled,
-                        };
-
-                        *no_conn.result.borrow_mut() = Some(result);
-                        no_conn.notify.notify_waiters();
-                    }
-                });
+                    let Some(cleanup) = self.spawn({
+                        let this = Rc::clone(&self);
+                        let query = Rc::clone(&query);
+                        let no_conn = Rc::clone(&no_conn);
+                        let arg = FetchArg::Shared(arg.into_shared());
+                        async move {
+                            OnlineHandler::wait().await;
 
-                let cleanup = handle.cleanup();
-                let map_handle = Cell::new(None);
+                            let result =
+                                match this.fetch_with_arg_inner(query, arg, id, count).await {
+                                    FetchResult::NoConnection(nc) => nc.wait().await,
+                                    FetchResult::Fresh(f) => FetchResultWaited::Fresh(f),
+                                    FetchResult::Stale(s) => FetchResultWaited::Stale(s),
+                                    FetchResult::Cancelled(r) => FetchResultWaited::Cancelled(r),
+                                };
 
-                query
-                    .link
-                    .with_entry(&self.query_cache.link_target, |e| match e {
-                        Entry::Occupied(mut o) => {
-                            let h = o.get_mut().future_handles.insert(handle);
-                            map_handle.set(Some(h));
+                            *no_conn.result.borrow_mut() = Some(result);
+                            no_conn.notify.notify_waiters();
                         }
-                        Entry::Vacant => {
-                            unreachable!();
+                    }) else {
+                        return FetchResult::Cancelled(CancelReason::Evicted);
+                    };
+
+                    // Client or entry was dropped before the connection returned, so the fetch will never finish
+                    cleanup.add_cleanup_on(CleanupOn::Abort, {
+                        let no_conn = Rc::clone(&no_conn);
+                        move || {
+                            *no_conn.result.borrow_mut() =
+                                Some(FetchResultWaited::Cancelled(CancelReason::Evicted));
+                            no_conn.notify.notify_waiters();
                         }
                     });
 
-                cleanup
-                    .add_cleanup(move || {
+                    let entry_handle =
                         query
                             .link
                             .with_entry(&self.query_cache.link_target, |e| match e {
-                                Entry::Occupied(mut o) => o.get_mut().future_handles.remove(
-                                    map_handle
-                                        .take()
-                                        .expect("cleanup should not be called twice"),
-                                ),
-                                Entry::Vacant => {}
+                                Entry::Occupied(mut o) => {
+                                    Some(o.get_mut().future_handles.insert(cleanup.abort_on_drop()))
+                                }
+                                Entry::Vacant => None,
                             });
-                    })
-                    .await;
+                    if let Some(entry_handle) = entry_handle {
+                        let entry_handle = Cell::new(Some(entry_handle));
+                        let this = Rc::downgrade(&self);
+                        let query = Rc::downgrade(&query);
+                        cleanup.add_cleanup_on(CleanupOn::Completion, move || {
+                            let (Some(this), Some(query), Some(entry_handle)) =
+                                (this.upgrade(), query.upgrade(), entry_handle.take())
+                            else {
+                                return;
+                            };
+                            // Dropped once the entry is released, as dropping it runs cleanup
+                            let abort =
+                                query
+                                    .link
+                                    .with_entry(&this.query_cache.link_target, |e| match e {
+                                        Entry::Occupied(mut o) => {
+                                            o.get_mut().future_handles.remove(entry_handle)
+                                        }
+                                        Entry::Vacant => None,
+                                    });
+                            drop(abort);
+                        });
+                    }
 
-                return FetchResult::NoConnection(NoConnection { inner: no_conn });
-            }
+                    return FetchResult::NoConnection(NoConnection { inner: no_conn });
+                }
 
-            let result = query.execute_with_arg(&arg).await;
-            let retry = query
-                .link
-                .with_entry(&self.query_cache.link_target, |e| match e {
-                    Entry::Occupied(mut o) if id == o.get().id => {
-                        let (result, ret) = match result {
-                            Ok(r) => {
-                                let r = Rc::new(r);
-                                (
-                                    QueryData::Ok(Rc::clone(&r), QueryStatus::Idle),
-                                    Retry::Return(FetchResult::Fresh(Ok(r))),
-                                )
-                            }
-                            Err(e) => {
-                                let e = Rc::new(e);
-                                let retry = resolve::resolve_retry(&self.opts, &query.opts);
-                                let (status, retry) =
-                                    retry.retry_delay(count, Rc::clone(&e)).map_or_else(
-                                        || {
-                                            (
-                                                QueryStatus::Idle,
-                                                Retry::Return(FetchResult::Fresh(Err(Rc::clone(
-                                                    &e,
-                                                )))),
-                                            )
-                                        },
-                                        |r| (QueryStatus::Loading, Retry::Retry(r)),
-                                    );
-                                (QueryData::Err(Rc::clone(&e), status), retry)
+                let executed = Instant::now();
+                let mut execute = pin!(query.execute_with_arg(arg.get()));
+                let result = match poll!(execute.as_mut()) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => {
+                        self.end_batch(&query);
+                        select! {
+                            result = execute => result,
+                            () = self.cancel_all.notified() => {
+                                return FetchResult::Cancelled(CancelReason::Explicit);
                             }
-                        };
-                        Listenable::set(&mut o.get_mut().data, result);
-                        ret
+                        }
                     }
-                    Entry::Occupied(_) | Entry::Vacant => Retry::Return(FetchResult::Stale(result)),
-                });
+                };
+                let duration = executed.elapsed();
+                let retry_config = resolve::resolve_retry(&self.opts, &query.opts);
+                let retry = query
+                    .link
+                    .with_entry(&self.query_cache.link_target, |e| match e {
+                        Entry::Occupied(mut o) if id == o.get().id => {
+                            o.get_mut().metrics.record(duration, result.is_ok());
+                            let (result, ret) = match result {
+                                Ok(r) => {
+                                    let r = Rc::new(r);
+                                    (
+                                        QueryData::Ok(Rc::clone(&r), QueryStatus::Idle),
+                                        Retry::Return(FetchResult::Fresh(Ok(r))),
+                                    )
+                                }
+                                Err(e) => {
+                                    let e = Rc::new(e);
+                                    let (status, retry) = retry_config
+                                        .retry_delay(count, Rc::clone(&e), started.elapsed())
+                                        .map_or_else(
+                                            || {
+                                                (
+                                                    QueryStatus::Idle,
+                                                    Retry::Return(FetchResult::Fresh(Err(
+                                                        Rc::clone(&e),
+                                                    ))),
+                                                )
+                                            },
+                                            |r| {
+                                                (
+                                                    QueryStatus::Loading,
+                                                    Retry::Retry(r, Rc::clone(&e)),
+                                                )
+                                            },
+                                        );
+                                    (QueryData::Err(Rc::clone(&e), status), retry)
+                                }
+                            };
+                            let entry = o.get_mut();
+                            let result = match result {
+                                // Keep the data from before the fetch in place of the error, or settle without any once out of retries
+                                QueryData::Err(_, status) if !cache_errors => match *entry.data {
+                                    QueryData::Ok(ref r, _) => QueryData::Ok(Rc::clone(r), status),
+                                    QueryData::Err(..) | QueryData::Pending(_) => {
+                                        QueryData::Pending(PendingStatus::from_query(status))
+                                    }
+                                },
+                                result => result,
+                            };
+                            match result {
+                                QueryData::Ok(..) => entry.data_updated_at = Some(Instant::now()),
+                                QueryData::Err(_, QueryStatus::Idle) => {
+                                    entry.error_updated_at = Some(Instant::now());
+                                }
+                                QueryData::Err(..) | QueryData::Pending(_) => {}
+                            }
+                            entry.set_data(result);
+                            entry.end_batch();
+                            ret
+                        }
+                        Entry::Occupied(mut o) => {
+                            o.get_mut().end_batch();
+                            Retry::Return(FetchResult::Stale(result.map(Rc::new).map_err(Rc::new)))
+                        }
+                        Entry::Vacant => {
+                            Retry::Return(FetchResult::Stale(result.map(Rc::new).map_err(Rc::new)))
+                        }
+                    });
 
-            let retry = match retry {
-                Retry::Return(r) => return r,
-                Retry::Retry(r) => r,
-            };
+                let retry = match retry {
+                    Retry::Return(r) => return r,
+                    Retry::Retry(r, e) => {
+                        retry_config.notify_retry(count, e);
+                        r
+                    }
+                };
 
-            sleep::sleep(retry).await;
+                select! {
+                    () = sleep::sleep(retry) => {}
+                    () = self.cancel_all.notified() => {
+                        return FetchResult::Cancelled(CancelReason::Explicit);
+                    }
+                }
+                count = count.checked_add(1).expect("retry count overflowed");
+            }
+        };
 
-            self.fetch_with_arg_inner(
-                query,
-                arg,
-                id,
-                count.checked_add(1).expect("retry count overflowed"),
-            )
-            .await
-        })
+        #[cfg(feature = "tracing")]
+        let fetch = crate::trace::in_span(span, fetch, crate::trace::fetch_outcome);
+        Box::pin(fetch)
     }
 }
@@ -2,23 +2,29 @@
 #![deny(clippy::future_not_send)]
 
 use std::{
-    fmt::{self, Debug, Formatter},
+    error::Error as StdError,
+    fmt::{self, Debug, Display, Formatter},
     future::Future,
     mem,
     pin::Pin,
-    sync::Arc,
-    thread,
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use futures::future;
 use tokio::{
     runtime::Builder,
-    sync::{mpsc, oneshot, Notify},
+    sync::{mpsc, oneshot},
     task::{self, LocalSet},
+    time,
 };
 
 use super::QueryClient;
 
+/// Longest [`SsrQueryClient::dehydrate`] waits for fetches in progress to finish
+const DEHYDRATE_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
 type SsrClientFn<'client> =
     dyn Fn(QueryClient<'client>) -> Pin<Box<dyn Future<Output = ()>>> + 'client + Send + Sync;
 
@@ -26,25 +32,56 @@ type SsrClientFn<'client> =
 enum SsrClientReq<'client> {
     With(SsrClientWithReq<'client>),
     Dehydrate(oneshot::Sender<String>),
+    /// Stops the thread as if it had died, without the [`SsrQueryClient`] being dropped
+    #[cfg(test)]
+    Stop,
 }
 
 struct SsrClientWithReq<'client> {
     f: Box<SsrClientFn<'client>>,
-    notify: Arc<Notify>,
+    done: oneshot::Sender<()>,
 }
 
 impl Debug for SsrClientWithReq<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("SsrClientWithReq")
             .field("f", &"..")
-            .field("res", &self.notify)
+            .field("done", &self.done)
             .finish()
     }
 }
 
+/// Error of a request made to an [`SsrQueryClient`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SsrClientError;
+
+impl Display for SsrClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "query client thread stopped before it could respond, or the future executing on it panicked"
+        )
+    }
+}
+
+impl StdError for SsrClientError {}
+
+impl<T> From<mpsc::error::SendError<T>> for SsrClientError {
+    fn from(_: mpsc::error::SendError<T>) -> Self {
+        Self
+    }
+}
+
+impl From<oneshot::error::RecvError> for SsrClientError {
+    fn from(_: oneshot::error::RecvError) -> Self {
+        Self
+    }
+}
+
 /// Allow usage of [`QueryClient`] in a multi-threaded context, without restricting futures to be unsend
 pub struct SsrQueryClient<'client> {
     tx: mpsc::Sender<SsrClientReq<'client>>,
+    worker: JoinHandle<()>,
 }
 
 impl Debug for SsrQueryClient<'_> {
@@ -54,10 +91,23 @@ impl Debug for SsrQueryClient<'_> {
 }
 
 impl<'client> SsrQueryClient<'client> {
-    /// Create new [`SsrQueryClient`]
+    /// Create new [`SsrQueryClient`], with room for a single request to be queued
+    /// See [`Self::with_capacity`]
     #[must_use = "No reason to create an SsrQueryClient if you don't use it"]
     pub fn new() -> Self {
-        let (tx, mut rx) = mpsc::channel::<SsrClientReq<'client>>(1);
+        Self::with_capacity(1)
+    }
+
+    /// Create new [`SsrQueryClient`], with room for `capacity` requests to be queued
+    ///
+    /// Requests are queued until the [`QueryClient`]'s thread receives them, which it does between executing futures
+    /// Once the queue is full, making a request will wait for there to be room before it is sent
+    ///
+    /// # Panics
+    /// Will panic if `capacity` is 0
+    #[must_use = "No reason to create an SsrQueryClient if you don't use it"]
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (tx, mut rx) = mpsc::channel::<SsrClientReq<'client>>(capacity);
         // SAFETY: LocalSet is aborted when SsrQueryClient is dropped, as tx will be dropped and rx.recv() will return None
         let mut rx: mpsc::Receiver<SsrClientReq<'static>> = unsafe { mem::transmute(rx) };
 
@@ -66,7 +116,7 @@ impl<'client> SsrQueryClient<'client> {
             .build()
             .expect("should be able to create runtime");
 
-        thread::spawn(move || {
+        let worker = thread::spawn(move || {
             let client = QueryClient::default();
             let local = LocalSet::new();
             let _guard = local.enter();
@@ -80,16 +130,34 @@ impl<'client> SsrQueryClient<'client> {
                                 let client = client.clone();
                                 async move {
                                     (with.f)(client).await;
-                                    with.notify.notify_one();
+                                    // If caller fails to await `with` and the future gets dropped, this send will fail
+                                    // Nothing to handle, just ignore
+                                    #[allow(clippy::let_underscore_must_use)]
+                                    let _ = with.done.send(());
                                 }
                             });
                         }
                         SsrClientReq::Dehydrate(res) => {
-                            // TODO
-                            // If caller fails to await `dehydrate` and the future gets dropped, this send will fail
-                            // Nothing to handle, just ignore
-                            drop(res.send("TODO".to_owned()));
+                            task::spawn_local({
+                                let client = client.clone();
+                                async move {
+                                    // Once timed out, whatever has loaded so far is used
+                                    drop(
+                                        time::timeout(
+                                            DEHYDRATE_IDLE_TIMEOUT,
+                                            client.wait_for_idle(),
+                                        )
+                                        .await,
+                                    );
+                                    // TODO
+                                    // If caller fails to await `dehydrate` and the future gets dropped, this send will fail
+                                    // Nothing to handle, just ignore
+                                    drop(res.send("TODO".to_owned()));
+                                }
+                            });
                         }
+                        #[cfg(test)]
+                        SsrClientReq::Stop => break,
                     }
                 }
                 handle.abort();
@@ -100,35 +168,80 @@ impl<'client> SsrQueryClient<'client> {
             let _ = rt.block_on(abortable);
         });
 
-        Self { tx }
+        Self { tx, worker }
+    }
+
+    /// Stop the [`QueryClient`]'s thread, blocking until it has finished
+    /// Any futures still executing on the thread, including ones spawned by the [`QueryClient`], are aborted
+    ///
+    /// # Errors
+    /// Will error if the [`QueryClient`]'s thread panicked
+    pub fn shutdown(self) -> Result<(), SsrClientError> {
+        let Self { tx, worker } = self;
+        drop(tx);
+        worker.join().map_err(|panic| {
+            log::error!("query client thread panicked: {panic:?}");
+            SsrClientError
+        })
     }
 
     /// Will execute the closure & await the returned future on the [`QueryClient`]'s thread
     /// Takes the [`QueryClient`] as a parameter
+    ///
+    /// # Errors
+    /// Will error if the [`QueryClient`]'s thread has stopped, or the future panics
     pub async fn with(
         &self,
         f: impl Fn(QueryClient<'client>) -> Pin<Box<dyn Future<Output = ()>>> + 'client + Send + Sync,
-    ) {
-        let notify = Arc::new(Notify::new());
+    ) -> Result<(), SsrClientError> {
+        let (done, rx) = oneshot::channel();
         self.tx
             .send(SsrClientReq::With(SsrClientWithReq {
                 f: Box::new(f),
-                notify: Arc::clone(&notify),
+                done,
             }))
-            .await
-            .expect("should not be able to fail while `self` is still alive");
-        notify.notified().await;
+            .await?;
+        Ok(rx.await?)
+    }
+
+    /// Will execute the closure & await the returned future on the [`QueryClient`]'s thread, returning its output
+    /// Takes the [`QueryClient`] as a parameter
+    ///
+    /// # Errors
+    /// Will error if the [`QueryClient`]'s thread has stopped, or the future panics
+    pub async fn with_result<T: Send + 'static>(
+        &self,
+        f: impl Fn(QueryClient<'client>) -> Pin<Box<dyn Future<Output = T>>> + 'client + Send + Sync,
+    ) -> Result<T, SsrClientError> {
+        let (res, rx) = oneshot::channel();
+        let res = Mutex::new(Some(res));
+        self.with(move |client| {
+            let fut = f(client);
+            // Closure is only called once, so the lock can't be poisoned
+            let res = res.lock().ok().and_then(|mut res| res.take());
+            Box::pin(async move {
+                let output = fut.await;
+                if let Some(res) = res {
+                    // If caller fails to await `with_result` and the future gets dropped, this send will fail
+                    // Nothing to handle, just ignore
+                    drop(res.send(output));
+                }
+            })
+        })
+        .await?;
+        Ok(rx.await?)
     }
 
     /// Will get the dehydrated state of the [`QueryClient`]
-    pub async fn dehydrate(&self) -> String {
+    /// Fetches still in progress, such as ones spawned by [`Self::with`] that outlive it, are waited for first so their results are included
+    /// If they haven't finished after 10s, the state is taken without them
+    ///
+    /// # Errors
+    /// Will error if the [`QueryClient`]'s thread has stopped
+    pub async fn dehydrate(&self) -> Result<String, SsrClientError> {
         let (res, rx) = oneshot::channel();
-        self.tx
-            .send(SsrClientReq::Dehydrate(res))
-            .await
-            .expect("should not be able to fail while `self` is still alive");
-        rx.await
-            .expect("should send back a response after completion of passed future")
+        self.tx.send(SsrClientReq::Dehydrate(res)).await?;
+        Ok(rx.await?)
     }
 }
 
@@ -137,3 +250,162 @@ impl Default for SsrQueryClient<'_> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use futures::future;
+    use tokio::{task, time};
+
+    use super::{SsrClientError, SsrClientReq, SsrQueryClient};
+    use crate::query::Query;
+
+    #[tokio::test]
+    async fn with_result() {
+        let client = SsrQueryClient::new();
+        let id = client
+            .with_result(|_| Box::pin(async { thread::current().id() }))
+            .await
+            .expect("client thread should be running");
+        assert_ne!(id, thread::current().id());
+    }
+
+    #[tokio::test]
+    async fn concurrent_with() {
+        let client = SsrQueryClient::new();
+        let with = |millis| {
+            let done = Arc::new(AtomicBool::new(false));
+            let client = &client;
+            async move {
+                client
+                    .with({
+                        let done = Arc::clone(&done);
+                        move |_| {
+                            let done = Arc::clone(&done);
+                            Box::pin(async move {
+                                time::sleep(Duration::from_millis(millis)).await;
+                                done.store(true, Ordering::SeqCst);
+                            })
+                        }
+                    })
+                    .await
+                    .expect("client thread should be running");
+                done.load(Ordering::SeqCst)
+            }
+        };
+
+        let (slow, fast) = tokio::join!(with(100), with(10));
+        assert!(slow);
+        assert!(fast);
+    }
+
+    #[tokio::test]
+    async fn dehydrate_waits_for_fetches() {
+        let client = SsrQueryClient::new();
+        let fetched = Arc::new(AtomicBool::new(false));
+        client
+            .with({
+                let fetched = Arc::clone(&fetched);
+                move |client| {
+                    let fetched = Arc::clone(&fetched);
+                    let query = Query::new(move || {
+                        let fetched = Arc::clone(&fetched);
+                        Box::pin(async move {
+                            time::sleep(Duration::from_millis(50)).await;
+                            fetched.store(true, Ordering::SeqCst);
+                            Ok::<i32, ()>(1_i32)
+                        })
+                    });
+                    client.prefetch(&query);
+                    Box::pin(async {
+                        // Let the detached fetch start
+                        task::yield_now().await;
+                    })
+                }
+            })
+            .await
+            .expect("client thread should be running");
+        assert!(!fetched.load(Ordering::SeqCst));
+
+        client
+            .dehydrate()
+            .await
+            .expect("client thread should be running");
+        assert!(fetched.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn capacity() {
+        let client = SsrQueryClient::with_capacity(4);
+        assert_eq!(client.tx.max_capacity(), 4);
+
+        let with = || client.with(|_| Box::pin(async {}));
+        let results = tokio::join!(with(), with(), with(), with(), with());
+        assert_eq!(results, (Ok(()), Ok(()), Ok(()), Ok(()), Ok(())));
+    }
+
+    #[tokio::test]
+    async fn panic_is_error() {
+        let client = SsrQueryClient::new();
+        let res = client
+            .with(|_| Box::pin(async { panic!("future executing on client thread panicked") }))
+            .await;
+        assert_eq!(res, Err(SsrClientError));
+        assert_eq!(client.with(|_| Box::pin(async {})).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn stopped_worker_is_error() {
+        let client = SsrQueryClient::new();
+        client
+            .tx
+            .send(SsrClientReq::Stop)
+            .await
+            .expect("client thread should be running");
+        while !client.worker.is_finished() {
+            time::sleep(Duration::from_millis(1)).await;
+        }
+
+        assert_eq!(
+            client.with(|_| Box::pin(async {})).await,
+            Err(SsrClientError)
+        );
+        assert_eq!(
+            client.with_result(|_| Box::pin(async { 1_i32 })).await,
+            Err(SsrClientError)
+        );
+        assert_eq!(client.dehydrate().await, Err(SsrClientError));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let client = SsrQueryClient::new();
+        let held = Arc::new(());
+        client
+            .with({
+                let held = Arc::clone(&held);
+                move |_| {
+                    let held = Arc::clone(&held);
+                    Box::pin(async move {
+                        task::spawn_local(async move {
+                            future::pending::<()>().await;
+                            drop(held);
+                        });
+                    })
+                }
+            })
+            .await
+            .expect("client thread should be running");
+
+        assert_eq!(client.shutdown(), Ok(()));
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+}
@@ -0,0 +1,2067 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Formatter},
+    rc::Rc,
+    time::Duration,
+};
+
+use futures::{future, StreamExt};
+use tokio::{task, time};
+
+use crate::{
+    cache::{mutation::MutationCache, query::QueryCache},
+    client::{CancelToken, ClientOpts, QueryClient},
+    config::{
+        error::{DisplayError, Error, ErrorDisplay, ErrorKind, StdError},
+        retry::{RetryAfter, RetryConfig},
+        CacheTime, NetworkMode, SetOption,
+    },
+    mutation::{Mutation, MutationCallbacks, MutationOpts},
+    query::{KeyedQuery, Query, QueryOpts},
+    status::{
+        CancelReason, FetchResult, MutateError, MutationData, PendingStatus, QueryData, QueryStatus,
+    },
+    test_harness,
+};
+
+#[test]
+fn weak_subscription_pruned() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let target = Rc::new(RefCell::new(0_i32));
+
+        client.subscribe_query_weak(&query, Rc::downgrade(&target), |t, _| {
+            *t.borrow_mut() += 1_i32;
+        });
+        client.fetch(&query).await;
+        assert_eq!(*target.borrow(), 1_i32);
+
+        drop(target);
+        client.fetch(&query).await;
+
+        let entry = query
+            .inner
+            .link
+            .borrow(&client.query_cache().link_target)
+            .expect("entry should still be cached");
+        assert_eq!(entry.data.listener_count(), 0);
+        assert!(!entry.cache_control.active());
+    });
+}
+
+#[test]
+fn optimistic_mutation_rollback() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let mutation = Mutation::new(|_: &i32| Box::pin(async { Err::<i32, ()>(()) }));
+
+        client.set_query_data(&query, 1_i32);
+        let res = client
+            .mutate_optimistic(&mutation, 2_i32, &query, 2_i32, true)
+            .await;
+
+        assert!(matches!(res, Err(MutateError::FnError(_))));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+    });
+}
+
+#[test]
+fn optimistic_mutation_replace() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v + 1_i32) })
+        });
+
+        client.set_query_data(&query, 1_i32);
+        let res = client
+            .mutate_optimistic(&mutation, 2_i32, &query, 2_i32, false)
+            .await;
+        assert!(matches!(res, Ok(r) if *r == 3_i32));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 2_i32));
+
+        let res = client
+            .mutate_optimistic(&mutation, 2_i32, &query, 2_i32, true)
+            .await;
+        assert!(matches!(res, Ok(r) if *r == 3_i32));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 3_i32));
+    });
+}
+
+#[test]
+fn dropping_client_aborts_prefetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let cache = Rc::clone(client.query_cache());
+        let started = Rc::new(Cell::new(false));
+        let query = Query::new({
+            let started = Rc::clone(&started);
+            move || {
+                started.set(true);
+                Box::pin(async {
+                    time::sleep(Duration::from_millis(50)).await;
+                    Ok::<i32, ()>(1_i32)
+                })
+            }
+        });
+
+        client.prefetch(&query);
+        task::yield_now().await;
+        assert!(started.get());
+
+        drop(client);
+        test_harness::advance(Duration::from_millis(100)).await;
+        assert!(matches!(cache.data(&query), Some(QueryData::Pending(_))));
+    });
+}
+
+#[test]
+fn remove_subscribed_query() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let last = Rc::new(RefCell::new(None));
+
+        let _guard = client.subscribe_query(&query, {
+            let last = Rc::clone(&last);
+            move |data| *last.borrow_mut() = Some(data)
+        });
+        client.fetch(&query).await;
+        assert!(matches!(*last.borrow(), Some(QueryData::Ok(ref d, _)) if **d == 1_i32));
+
+        let removed = client.remove_query(&query);
+        assert!(matches!(removed, Some(QueryData::Ok(d, _)) if *d == 1_i32));
+        assert!(matches!(*last.borrow(), Some(QueryData::Pending(_))));
+        assert!(client.query_data(&query).is_none());
+    });
+}
+
+#[test]
+fn reset_subscribed_query() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                let count = count.get();
+                Box::pin(async move { Ok::<i32, ()>(count) })
+            }
+        });
+        let last = Rc::new(RefCell::new(None));
+
+        let _guard = client.subscribe_query(&query, {
+            let last = Rc::clone(&last);
+            move |data| *last.borrow_mut() = Some(data)
+        });
+        client.fetch(&query).await;
+
+        let reset = client.reset_query(&query);
+        assert!(matches!(reset, Some(QueryData::Ok(d, _)) if *d == 1_i32));
+        assert!(matches!(*last.borrow(), Some(QueryData::Pending(_))));
+
+        client.fetch(&query).await;
+        assert!(matches!(*last.borrow(), Some(QueryData::Ok(ref d, _)) if **d == 2_i32));
+    });
+}
+
+#[test]
+fn retries_until_success() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move || {
+                    count.set(count.get() + 1_i32);
+                    let count = count.get();
+                    Box::pin(async move {
+                        match count {
+                            5_i32.. => Ok::<i32, ()>(count),
+                            _ => Err(()),
+                        }
+                    })
+                }
+            },
+            QueryOpts::with_retry(
+                RetryConfig::default()
+                    .infinite()
+                    .always(Duration::from_millis(1)),
+            ),
+        );
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |data| seen.borrow_mut().push(data)
+        });
+        let res = client.fetch(&query).await;
+
+        assert!(matches!(res, FetchResult::Fresh(Ok(d)) if *d == 5_i32));
+        assert_eq!(count.get(), 5_i32);
+        let seen = seen.borrow();
+        let (last, retries) = seen.split_last().expect("should have been notified");
+        assert!(matches!(*last, QueryData::Ok(ref d, QueryStatus::Idle) if **d == 5_i32));
+        assert_eq!(
+            retries
+                .iter()
+                .filter(|d| matches!(**d, QueryData::Err(_, QueryStatus::Loading)))
+                .count(),
+            4
+        );
+    });
+}
+
+#[test]
+fn fetch_transitions() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                let count = count.get();
+                Box::pin(async move {
+                    // Still fetching after its first poll, so the loading status is notified on its own
+                    task::yield_now().await;
+                    Ok::<i32, ()>(count)
+                })
+            }
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |data| seen.borrow_mut().push(data)
+        });
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Pending(_))
+        ));
+
+        client.fetch(&query).await;
+        client.fetch(&query).await;
+
+        let seen = seen.borrow();
+        let [ref first, ref previous, ref second] = *seen.as_slice() else {
+            panic!("expected 3 notifications, got {seen:?}");
+        };
+        assert!(matches!(*first, QueryData::Ok(ref d, QueryStatus::Idle) if **d == 1_i32));
+        assert!(!first.is_fetching());
+        assert!(matches!(*previous, QueryData::Ok(ref d, QueryStatus::Loading) if **d == 1_i32));
+        assert!(previous.is_previous_data() && previous.is_fetching());
+        assert!(matches!(*second, QueryData::Ok(ref d, QueryStatus::Idle) if **d == 2_i32));
+        assert!(!second.is_previous_data());
+    });
+}
+
+#[test]
+fn ready_fetch_notifies_once() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_sync(|| Ok::<i32, ()>(2_i32));
+        client.set_query_data(&query, 1_i32);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |data| seen.borrow_mut().push(data)
+        });
+        client.fetch(&query).await;
+
+        assert!(matches!(
+            seen.borrow().as_slice(),
+            [QueryData::Ok(ref d, QueryStatus::Idle)] if **d == 2_i32
+        ));
+    });
+}
+
+/// Counts how many times it's been cloned
+struct CloneCounter {
+    clones: Rc<Cell<i32>>,
+    data: Vec<u8>,
+}
+
+impl Clone for CloneCounter {
+    fn clone(&self) -> Self {
+        self.clones.set(self.clones.get() + 1_i32);
+        Self {
+            clones: Rc::clone(&self.clones),
+            data: self.data.clone(),
+        }
+    }
+}
+
+#[test]
+fn fetch_with_ref() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_param(|arg: &CloneCounter| {
+            let len = arg.data.len();
+            Box::pin(async move { Ok::<usize, ()>(len) })
+        });
+        let arg = CloneCounter {
+            clones: Rc::new(Cell::new(0_i32)),
+            data: vec![0; 1024],
+        };
+
+        assert!(matches!(
+            client.fetch_with_ref(&query, &arg).await,
+            FetchResult::Fresh(Ok(d)) if *d == 1024
+        ));
+        assert_eq!(arg.clones.get(), 0_i32);
+    });
+}
+
+#[test]
+fn cancelled_fetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| {
+            Box::pin(async {
+                time::sleep(Duration::from_millis(50)).await;
+                Ok::<i32, ()>(2_i32)
+            })
+        });
+
+        client.set_query_data(&query, 1_i32);
+        let res = client
+            .fetch_with_cancel(&query, (), time::sleep(Duration::from_millis(1)))
+            .await;
+        assert!(matches!(
+            res,
+            FetchResult::Cancelled(CancelReason::Explicit)
+        ));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 1_i32
+        ));
+
+        test_harness::advance(Duration::from_millis(100)).await;
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+    });
+}
+
+#[test]
+fn cancel_all() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let slow = |n: i32| {
+            Query::new(move || {
+                Box::pin(async move {
+                    time::sleep(Duration::from_millis(50)).await;
+                    Ok::<i32, ()>(n)
+                })
+            })
+        };
+        let queries = [slow(1_i32), slow(2_i32), slow(3_i32)];
+        let _guard = client.subscribe_query(&queries[0], |_| {});
+
+        let fetches = future::join_all(queries.iter().map(|q| client.fetch(q)));
+        let cancel = async {
+            time::sleep(Duration::from_millis(1)).await;
+            client.cancel_all();
+        };
+        let (results, ()) = futures::join!(fetches, cancel);
+
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, FetchResult::Cancelled(CancelReason::Explicit))));
+        assert_eq!(client.peek(&queries[0]).observer_count, 1);
+    });
+}
+
+#[test]
+fn cancel_all_keeps_guards_valid() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_opts(
+            || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+            QueryOpts::with_cache_time(CacheTime::Duration(Duration::from_secs(5))),
+        );
+
+        let guard = client.subscribe_query(&query, |_| {});
+        let (_, retained) = client.fetch_and_retain(&query, ()).await;
+        let ids = client.active_query_ids();
+        client.cancel_all();
+        assert_eq!(client.active_query_ids(), ids);
+
+        drop(guard);
+        assert_eq!(client.peek(&query).observer_count, 0);
+        assert_eq!(client.active_query_ids(), ids);
+
+        drop(retained);
+        assert!(client.active_query_ids().is_empty());
+        test_harness::advance(Duration::from_secs(6)).await;
+        assert!(client.query_data(&query).is_none());
+    });
+}
+
+#[test]
+fn cancel_all_settles_pending() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| {
+            Box::pin(async {
+                time::sleep(Duration::from_secs(1)).await;
+                Ok::<i32, ()>(1_i32)
+            })
+        });
+
+        let cancel = async {
+            test_harness::advance(Duration::from_millis(1)).await;
+            client.cancel_all();
+        };
+        let (res, ()) = futures::join!(client.fetch(&query), cancel);
+        assert!(matches!(
+            res,
+            FetchResult::Cancelled(CancelReason::Explicit)
+        ));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Pending(PendingStatus::Idle))
+        ));
+        assert!(!client.peek(&query).is_fetching);
+    });
+}
+
+#[test]
+fn cancel_token() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let slow = |n: i32| {
+            Query::new(move || {
+                Box::pin(async move {
+                    time::sleep(Duration::from_millis(50)).await;
+                    Ok::<i32, ()>(n)
+                })
+            })
+        };
+        let queries = [slow(1_i32), slow(2_i32)];
+        let token = CancelToken::new();
+        for query in &queries {
+            token.hold(client.subscribe_query(query, |_| {}));
+        }
+        assert_eq!(client.peek(&queries[0]).observer_count, 1);
+
+        let fetches = future::join_all(
+            queries
+                .iter()
+                .map(|q| client.fetch_with_cancel(q, (), token.cancelled())),
+        );
+        let cancel = async {
+            time::sleep(Duration::from_millis(1)).await;
+            token.clone().cancel();
+        };
+        let (results, ()) = futures::join!(fetches, cancel);
+
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, FetchResult::Cancelled(CancelReason::Explicit))));
+        assert!(token.is_cancelled());
+        for query in &queries {
+            assert_eq!(client.peek(query).observer_count, 0);
+        }
+    });
+}
+
+#[test]
+fn wait_for_idle() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let slow = |ms: u64| {
+            Query::new(move || {
+                Box::pin(async move {
+                    time::sleep(Duration::from_millis(ms)).await;
+                    Ok::<i32, ()>(1_i32)
+                })
+            })
+        };
+        let queries = [slow(10), slow(30)];
+
+        client.wait_for_idle().await;
+        for query in &queries {
+            client.prefetch(query);
+        }
+        // Prefetches are spawned, so let them start
+        task::yield_now().await;
+        client.wait_for_idle().await;
+
+        assert!(queries
+            .iter()
+            .all(|q| matches!(client.query_data(q), Some(QueryData::Ok(..)))));
+    });
+}
+
+#[test]
+fn keyed_query_invalidate() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let query = KeyedQuery::new({
+            let calls = Rc::clone(&calls);
+            move |key: &i32| {
+                calls.borrow_mut().push(*key);
+                let key = *key;
+                Box::pin(async move { Ok::<i32, ()>(key * 10_i32) })
+            }
+        });
+
+        let _one = client.subscribe_keyed(&query, &1_i32, |_| {});
+        let _two = client.subscribe_keyed(&query, &2_i32, |_| {});
+        client.fetch_keyed(&query, 1_i32).await;
+        client.fetch_keyed(&query, 2_i32).await;
+        assert!(
+            matches!(client.keyed_query_data(&query, &1_i32), Some(QueryData::Ok(d, _)) if *d == 10_i32)
+        );
+        assert!(
+            matches!(client.keyed_query_data(&query, &2_i32), Some(QueryData::Ok(d, _)) if *d == 20_i32)
+        );
+
+        client.invalidate_keyed(&query, &1_i32);
+        task::yield_now().await;
+        assert_eq!(*calls.borrow(), [1_i32, 2_i32, 1_i32]);
+    });
+}
+
+#[test]
+fn seed_query() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let list =
+            Query::new(|| Box::pin(async { Ok::<_, ()>(vec![(1_i32, "one"), (2_i32, "two")]) }));
+        let fetched = Rc::new(Cell::new(0_i32));
+        let detail = KeyedQuery::new({
+            let fetched = Rc::clone(&fetched);
+            move |_: &i32| {
+                fetched.set(fetched.get() + 1_i32);
+                Box::pin(async { Ok::<&str, ()>("fetched") })
+            }
+        });
+
+        if let FetchResult::Fresh(Ok(items)) = client.fetch(&list).await {
+            for &(id, name) in items.iter() {
+                client.seed_query(&detail, &id, name);
+            }
+        }
+
+        assert!(matches!(
+            client.keyed_query_data(&detail, &2_i32),
+            Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == "two"
+        ));
+        assert_eq!(fetched.get(), 0_i32);
+    });
+}
+
+#[test]
+fn invalidate_where_name() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let keyed = |name: &'static str| {
+            let calls = Rc::clone(&calls);
+            KeyedQuery::new_named(name, move |key: &i32| {
+                calls.borrow_mut().push((name, *key));
+                Box::pin(async { Ok::<(), ()>(()) })
+            })
+        };
+        let posts = keyed("posts");
+        let users = keyed("users");
+
+        let _guards = [
+            client.subscribe_keyed(&posts, &1_i32, |_| {}),
+            client.subscribe_keyed(&posts, &2_i32, |_| {}),
+            client.subscribe_keyed(&users, &1_i32, |_| {}),
+        ];
+        calls.borrow_mut().clear();
+
+        client.invalidate_where(|d| d.name().is_some_and(|n| n.starts_with("post")));
+        task::yield_now().await;
+
+        let mut calls = calls.borrow().clone();
+        calls.sort_unstable();
+        assert_eq!(calls, [("posts", 1_i32), ("posts", 2_i32)]);
+    });
+}
+
+#[test]
+fn retry_max_elapsed() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move || {
+                    count.set(count.get() + 1_i32);
+                    Box::pin(async { Err::<i32, ()>(()) })
+                }
+            },
+            QueryOpts::with_retry(
+                RetryConfig::default()
+                    .infinite()
+                    .always(Duration::from_secs(1))
+                    .max_elapsed(Duration::from_secs(10)),
+            ),
+        );
+
+        let res = client.fetch(&query).await;
+        assert!(matches!(res, FetchResult::Fresh(Err(_))));
+        // First attempt, then a retry each second up to the 10s cap
+        assert_eq!(count.get(), 11_i32);
+    });
+}
+
+#[test]
+fn retry_on_kind() {
+    #[derive(Debug)]
+    struct HttpError(u16);
+
+    struct Status(u16);
+
+    impl ErrorKind for Status {}
+
+    impl ErrorDisplay for HttpError {
+        fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "status {}", self.0)
+        }
+    }
+
+    impl Error for HttpError {
+        fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>> {
+            Some(Box::new(Status(self.0)))
+        }
+    }
+
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let attempts = |status: u16| {
+            let count = Rc::new(Cell::new(0_i32));
+            let query = Query::new_with_opts(
+                {
+                    let count = Rc::clone(&count);
+                    move || {
+                        count.set(count.get() + 1_i32);
+                        Box::pin(async move { Err::<i32, _>(HttpError(status)) })
+                    }
+                },
+                QueryOpts::with_retry(
+                    RetryConfig::default()
+                        .num(2)
+                        .always(Duration::from_secs(1))
+                        .retry_on_kind(|s: &Status| s.0 >= 500),
+                ),
+            );
+            let client = client.clone();
+            async move {
+                client.fetch(&query).await;
+                count.get()
+            }
+        };
+
+        // Retryable errors are still limited by the policy they were added on top of
+        assert_eq!(attempts(503).await, 3_i32);
+        assert_eq!(attempts(404).await, 1_i32);
+    });
+}
+
+#[test]
+fn retry_after() {
+    #[derive(Debug)]
+    struct RateLimited(Duration);
+
+    impl ErrorDisplay for RateLimited {
+        fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "retry after {}s", self.0.as_secs())
+        }
+    }
+
+    impl Error for RateLimited {
+        fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>> {
+            None
+        }
+
+        fn as_retry_after(&self) -> Option<&dyn RetryAfter> {
+            Some(self)
+        }
+    }
+
+    impl RetryAfter for RateLimited {
+        fn retry_after(&self) -> Option<Duration> {
+            Some(self.0)
+        }
+    }
+
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move || {
+                    count.set(count.get() + 1_i32);
+                    let res = if count.get() == 1_i32 {
+                        Err(RateLimited(Duration::from_secs(2)))
+                    } else {
+                        Ok(1_i32)
+                    };
+                    Box::pin(async move { res })
+                }
+            },
+            QueryOpts::with_retry(
+                RetryConfig::default().backoff(Duration::from_secs(10), Duration::from_secs(30)),
+            ),
+        );
+
+        let started = time::Instant::now();
+        let res = client.fetch(&query).await;
+        assert!(matches!(res, FetchResult::Fresh(Ok(_))));
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+    });
+}
+
+#[test]
+fn retry_observer() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let query = Query::new_with_opts(
+            || Box::pin(async { Err::<i32, ()>(()) }),
+            QueryOpts::with_retry(
+                RetryConfig::default()
+                    .num(3)
+                    .always(Duration::from_secs(1))
+                    .on_retry({
+                        let seen = Rc::clone(&seen);
+                        move |count, _| seen.borrow_mut().push(count)
+                    }),
+            ),
+        );
+
+        let res = client.fetch(&query).await;
+        assert!(matches!(res, FetchResult::Fresh(Err(_))));
+        // Called before each of the 3 retries, but not after the final failure
+        assert_eq!(*seen.borrow(), [1, 2, 3]);
+    });
+}
+
+#[test]
+fn mutation_context_callbacks() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let callbacks = MutationCallbacks::with_context(|v: &mut i32| {
+            let context = *v + 1_i32;
+            Box::pin(async move { context })
+        })
+        .on_success({
+            let seen = Rc::clone(&seen);
+            move |r, _, context: &i32| {
+                seen.borrow_mut().push((*r, *context));
+                Box::pin(async {})
+            }
+        })
+        .on_settled({
+            let seen = Rc::clone(&seen);
+            move |r, _, context: &i32| {
+                seen.borrow_mut()
+                    .push((*r.expect("mutation should succeed"), *context));
+                Box::pin(async {})
+            }
+        });
+
+        let res = client
+            .mutate(&mutation, 2_i32, None, Some(callbacks.into()))
+            .await;
+        assert!(matches!(res, Ok(r) if *r == 4_i32));
+        assert_eq!(*seen.borrow(), [(4_i32, 3_i32), (4_i32, 3_i32)]);
+    });
+}
+
+#[test]
+fn mutation_default_and_call_callbacks() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let callbacks = |name: &'static str, context: i32| {
+            let seen = Rc::clone(&seen);
+            MutationCallbacks::with_context(move |_: &mut i32| Box::pin(async move { context }))
+                .on_success(move |r, _, context: &i32| {
+                    seen.borrow_mut().push((name, *r, *context));
+                    Box::pin(async {})
+                })
+                .into()
+        };
+
+        let default_cb = callbacks("default", 1_i32);
+        let res = client
+            .mutate(
+                &mutation,
+                2_i32,
+                Some(&default_cb),
+                Some(callbacks("call", 2_i32)),
+            )
+            .await;
+        assert!(matches!(res, Ok(r) if *r == 4_i32));
+        assert_eq!(
+            *seen.borrow(),
+            [("default", 4_i32, 1_i32), ("call", 4_i32, 2_i32)]
+        );
+    });
+}
+
+#[test]
+fn query_with_opts_cached_separately() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                let count = count.get();
+                Box::pin(async move { Ok::<i32, ()>(count) })
+            }
+        });
+        let long = query.with_opts(QueryOpts::with_cache_time(CacheTime::Infinite));
+
+        client.fetch(&query).await;
+        client.fetch(&long).await;
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+        assert!(matches!(client.query_data(&long), Some(QueryData::Ok(d, _)) if *d == 2_i32));
+
+        drop(client.remove_query(&query));
+        assert!(client.query_data(&query).is_none());
+        assert!(client.query_data(&long).is_some());
+    });
+}
+
+#[test]
+fn fetch_metrics() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move || {
+                    count.set(count.get() + 1_i32);
+                    let count = count.get();
+                    Box::pin(async move {
+                        time::sleep(Duration::from_millis(5)).await;
+                        match count {
+                            1_i32 => Ok::<i32, ()>(count),
+                            _ => Err(()),
+                        }
+                    })
+                }
+            },
+            QueryOpts::with_retry(RetryConfig::none()),
+        );
+
+        client.fetch(&query).await;
+        client.fetch(&query).await;
+
+        let metrics = client
+            .query_metrics(&query)
+            .expect("query should be cached");
+        assert_eq!(metrics.durations().len(), 2);
+        assert!(metrics.durations().all(|d| d >= Duration::from_millis(5)));
+        assert_eq!(metrics.successes(), 1);
+        assert_eq!(metrics.errors(), 1);
+    });
+}
+
+#[test]
+fn peek_during_fetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| {
+            Box::pin(async {
+                time::sleep(Duration::from_millis(20)).await;
+                Ok::<i32, ()>(1_i32)
+            })
+        });
+
+        let peek = client.peek(&query);
+        assert!(peek.data.is_none() && !peek.is_fetching);
+
+        let _guard = client.subscribe_query(&query, |_| {});
+        client.prefetch(&query);
+        task::yield_now().await;
+        let peek = client.peek(&query);
+        assert!(peek.is_fetching);
+        assert_eq!(peek.observer_count, 1);
+
+        test_harness::advance(Duration::from_millis(50)).await;
+        let peek = client.peek(&query);
+        assert!(!peek.is_fetching);
+        assert!(matches!(peek.data, Some(QueryData::Ok(d, _)) if *d == 1_i32));
+    });
+}
+
+#[test]
+fn invalidate_refetches_with_subscribed_arg() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let args = Rc::new(RefCell::new(Vec::new()));
+        let query = Query::new_with_param({
+            let args = Rc::clone(&args);
+            move |&arg: &i32| {
+                args.borrow_mut().push(arg);
+                Box::pin(async move { Ok::<i32, ()>(arg) })
+            }
+        });
+
+        let _guard = client.subscribe_query_with_arg(&query, 5_i32, |_| {});
+        client.invalidate_query(&query);
+        task::yield_now().await;
+
+        assert_eq!(*args.borrow(), [5_i32]);
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 5_i32));
+    });
+}
+
+#[test]
+fn invalidate_and_await() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let version = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_param({
+            let version = Rc::clone(&version);
+            move |&arg: &i32| {
+                version.set(version.get() + 1_i32);
+                let version = version.get();
+                Box::pin(async move { Ok::<i32, ()>(arg * 10_i32 + version) })
+            }
+        });
+
+        assert!(client.invalidate_and_await(&query).await.is_none());
+
+        let guard = client.subscribe_query_with_arg(&query, 5_i32, |_| {});
+        client.fetch_with_arg(&query, 5_i32).await;
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 51_i32));
+
+        let res = client.invalidate_and_await(&query).await;
+        assert!(matches!(res, Some(FetchResult::Fresh(Ok(d))) if *d == 52_i32));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 52_i32));
+
+        // Refetched even once inactive, as the result is waited for
+        drop(guard);
+        let res = client.invalidate_and_await(&query).await;
+        assert!(matches!(res, Some(FetchResult::Fresh(Ok(d))) if *d == 53_i32));
+    });
+}
+
+#[test]
+fn invalidate_and_await_last_arg() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let version = Rc::new(Cell::new(0_i32));
+        let no_param = Query::new({
+            let version = Rc::clone(&version);
+            move || {
+                version.set(version.get() + 1_i32);
+                let version = version.get();
+                Box::pin(async move { Ok::<i32, ()>(version) })
+            }
+        });
+        let with_param = Query::new_with_param(|&arg: &i32| {
+            Box::pin(async move { Ok::<i32, ()>(arg * 10_i32) })
+        });
+
+        // Subscribed without an arg or fetching, only possible for queries that don't take one
+        let _no_param = client.subscribe_query(&no_param, |_| {});
+        let res = client.invalidate_and_await(&no_param).await;
+        assert!(matches!(res, Some(FetchResult::Fresh(Ok(d))) if *d == 1_i32));
+
+        // Refetched with the arg it was last fetched with
+        let _with_param = client.subscribe_query(&with_param, |_| {});
+        assert!(client.invalidate_and_await(&with_param).await.is_none());
+        client.prefetch_with_arg(&with_param, 2_i32);
+        task::yield_now().await;
+        client.set_query_data(&with_param, 0_i32);
+        let res = client.invalidate_and_await(&with_param).await;
+        assert!(matches!(res, Some(FetchResult::Fresh(Ok(d))) if *d == 20_i32));
+    });
+}
+
+#[test]
+fn superseded_fetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_opts(
+            || Box::pin(async { Err::<i32, ()>(()) }),
+            QueryOpts::with_retry(
+                RetryConfig::default()
+                    .infinite()
+                    .always(Duration::from_millis(20)),
+            ),
+        );
+
+        let fetch = client.fetch(&query);
+        let replace = async {
+            time::sleep(Duration::from_millis(5)).await;
+            client.remove_query(&query);
+            client.set_query_data(&query, 1_i32);
+        };
+        let (res, ()) = tokio::join!(fetch, replace);
+
+        assert!(matches!(
+            res,
+            FetchResult::Cancelled(CancelReason::Superseded)
+        ));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+    });
+}
+
+#[test]
+fn standalone_mutation_retries() {
+    test_harness::run_local(async {
+        let count = Rc::new(Cell::new(0_i32));
+        let mutation = Mutation::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move |_: &i32| {
+                    count.set(count.get() + 1_i32);
+                    Box::pin(async { Err::<i32, ()>(()) })
+                }
+            },
+            MutationOpts::with_retry(RetryConfig::default().num(3).always(Duration::from_secs(1))),
+        );
+
+        let res = mutation
+            .execute_with_opts(&1_i32, MutationOpts::new())
+            .await;
+        assert!(matches!(res, Err(MutateError::FnError(_))));
+        // First attempt, then each of the 3 retries
+        assert_eq!(count.get(), 4_i32);
+
+        count.set(0_i32);
+        let res = mutation
+            .execute_with_opts(&1_i32, RetryConfig::none())
+            .await;
+        assert!(matches!(res, Err(MutateError::FnError(_))));
+    });
+}
+
+#[test]
+fn sync_query() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_sync({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                Ok::<i32, ()>(1_i32)
+            }
+        });
+
+        assert!(matches!(client.fetch(&query).await, FetchResult::Fresh(Ok(d)) if *d == 1_i32));
+        assert_eq!(count.get(), 1_i32);
+        assert!(
+            matches!(client.query_data(&query), Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 1_i32)
+        );
+    });
+}
+
+#[test]
+fn map_err_query() {
+    #[derive(Debug)]
+    struct Wrapped(i32);
+
+    impl ErrorDisplay for Wrapped {
+        fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "wrapped {}", self.0)
+        }
+    }
+
+    impl Error for Wrapped {
+        fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>> {
+            None
+        }
+    }
+
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query =
+            Query::new(|| Box::pin(async { Err::<i32, ()>(()) })).map_err(|()| Wrapped(1_i32));
+
+        let res = client.fetch(&query).await;
+        assert!(matches!(res, FetchResult::Fresh(Err(e)) if e.0 == 1_i32));
+        assert!(matches!(client.query_data(&query), Some(QueryData::Err(e, _)) if e.0 == 1_i32));
+    });
+}
+
+#[test]
+fn default_with_opts_resolves_as_default() {
+    let inherrit = QueryClient::default();
+    let concrete = QueryClient::default_with_opts();
+    let query = QueryOpts::<()>::new();
+
+    for client in [&inherrit, &concrete] {
+        assert!(matches!(
+            client.inner.resolved.cache_time(&query),
+            CacheTime::Duration(d) if d == Duration::from_mins(5)
+        ));
+        assert!(matches!(
+            client.inner.resolved.network_mode(&query),
+            NetworkMode::Online
+        ));
+    }
+
+    // Only the concrete client's options would take priority over any other defaults
+    assert!(matches!(
+        inherrit.inner.opts.cache_time,
+        SetOption::Inherrit
+    ));
+    assert!(matches!(concrete.inner.opts.cache_time, SetOption::Set(_)));
+}
+
+#[test]
+fn reentrant_set_query_data() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+        let _guard = client.subscribe_query(&query, {
+            let client = client.clone();
+            let query = query.clone();
+            move |data| {
+                if matches!(data, QueryData::Ok(d, _) if *d == 1_i32) {
+                    client.set_query_data(&query, 2_i32);
+                }
+            }
+        });
+        client.fetch(&query).await;
+
+        assert!(matches!(client.query_data(&query), Some(QueryData::Ok(d, _)) if *d == 2_i32));
+    });
+}
+
+#[test]
+fn set_query_error() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let seen = Rc::new(RefCell::new(None));
+
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |data| *seen.borrow_mut() = Some(data)
+        });
+        client.set_query_error(&query, ());
+
+        assert!(matches!(
+            *seen.borrow(),
+            Some(QueryData::Err(_, QueryStatus::Idle))
+        ));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Err(_, QueryStatus::Idle))
+        ));
+    });
+}
+
+#[test]
+fn query_stream() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                let count = count.get();
+                Box::pin(async move {
+                    // Still fetching after its first poll, so the loading status is notified on its own
+                    task::yield_now().await;
+                    Ok::<i32, ()>(count)
+                })
+            }
+        });
+
+        let mut stream = client.query_stream(&query);
+        client.fetch(&query).await;
+        client.fetch(&query).await;
+
+        assert!(
+            matches!(stream.next().await, Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 1_i32)
+        );
+        assert!(
+            matches!(stream.next().await, Some(QueryData::Ok(d, QueryStatus::Loading)) if *d == 1_i32)
+        );
+        assert!(
+            matches!(stream.next().await, Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 2_i32)
+        );
+
+        drop(stream);
+        let entry = query
+            .inner
+            .link
+            .borrow(&client.query_cache().link_target)
+            .expect("entry should still be cached");
+        assert_eq!(entry.data.listener_count(), 0);
+    });
+}
+
+#[test]
+fn mutation_stream() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+
+        let mut stream = client.mutation_stream(&mutation);
+        let res = client
+            .mutate::<_, _, _, ()>(&mutation, 2_i32, None, None)
+            .await;
+        assert!(matches!(res, Ok(d) if *d == 4_i32));
+
+        assert!(matches!(
+            stream.next().await,
+            Some(MutationData::Loading(_))
+        ));
+        assert!(matches!(stream.next().await, Some(MutationData::Ok(d)) if *d == 4_i32));
+    });
+}
+
+#[test]
+fn mutate_spawn() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+
+        let mut stream = client.mutation_stream(&mutation);
+        let _guard = client.mutate_spawn::<_, _, _, ()>(&mutation, 2_i32, None);
+
+        let loading = stream.next().await.expect("stream should yield");
+        assert!(matches!(loading.variables(), Some(v) if **v == 2_i32));
+        assert!(matches!(stream.next().await, Some(MutationData::Ok(d)) if *d == 4_i32));
+    });
+}
+
+#[test]
+fn dropping_client_aborts_mutate_spawn() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let cache = Rc::clone(client.mutation_cache());
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move {
+                time::sleep(Duration::from_millis(50)).await;
+                Ok::<i32, ()>(v)
+            })
+        });
+
+        let guard = client.mutate_spawn::<_, _, _, ()>(&mutation, 2_i32, None);
+        task::yield_now().await;
+        assert!(matches!(
+            cache.data(&mutation),
+            Some(MutationData::Loading(_))
+        ));
+
+        drop(client);
+        test_harness::advance(Duration::from_millis(100)).await;
+        assert!(matches!(
+            cache.data(&mutation),
+            Some(MutationData::Loading(_))
+        ));
+        drop(guard);
+    });
+}
+
+#[test]
+fn mutation_data() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+        assert!(client.mutation_data(&mutation).is_none());
+
+        client
+            .mutate::<_, _, _, ()>(&mutation, 2_i32, None, None)
+            .await
+            .expect("mutation should succeed");
+        assert!(matches!(
+            client.mutation_data(&mutation),
+            Some(MutationData::Ok(d)) if *d == 4_i32
+        ));
+    });
+}
+
+#[test]
+fn shared_mutation_cache() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let other = QueryClient::new_with_caches(
+            ClientOpts::new(),
+            Rc::new(QueryCache::default()),
+            Rc::clone(client.mutation_cache()),
+        );
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v * 2_i32) })
+        });
+
+        other
+            .mutate::<_, _, _, ()>(&mutation, 2_i32, None, None)
+            .await
+            .expect("mutation should succeed");
+        assert!(matches!(
+            client.mutation_data(&mutation),
+            Some(MutationData::Ok(d)) if *d == 4_i32
+        ));
+    });
+}
+
+#[test]
+fn shared_cache_joins_fetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let other = QueryClient::new_with_caches(
+            ClientOpts::new(),
+            Rc::clone(client.query_cache()),
+            Rc::new(MutationCache::default()),
+        );
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                Box::pin(async {
+                    time::sleep(Duration::from_millis(10)).await;
+                    Ok::<i32, ()>(1_i32)
+                })
+            }
+        });
+
+        let results = future::join_all([client.fetch(&query), other.fetch(&query)]).await;
+        assert_eq!(count.get(), 1_i32);
+        assert!(results
+            .iter()
+            .all(|r| matches!(*r, FetchResult::Fresh(Ok(ref d)) if **d == 1_i32)));
+
+        // Finished, so the next fetch runs the query again
+        other.fetch(&query).await;
+        assert_eq!(count.get(), 2_i32);
+    });
+}
+
+#[test]
+fn shared_cache_prefetch_joins_fetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let other = QueryClient::new_with_caches(
+            ClientOpts::new(),
+            Rc::clone(client.query_cache()),
+            Rc::new(MutationCache::default()),
+        );
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new({
+            let count = Rc::clone(&count);
+            move || {
+                count.set(count.get() + 1_i32);
+                Box::pin(async {
+                    time::sleep(Duration::from_millis(10)).await;
+                    Ok::<i32, ()>(1_i32)
+                })
+            }
+        });
+
+        client.prefetch(&query);
+        task::yield_now().await;
+        let result = other.fetch_with_arg(&query, ()).await;
+        assert_eq!(count.get(), 1_i32);
+        assert!(matches!(result, FetchResult::Fresh(Ok(d)) if *d == 1_i32));
+
+        // The other way round, a prefetch joins a fetch in progress
+        future::join(client.fetch_with_arg(&query, ()), async {
+            task::yield_now().await;
+            other.prefetch(&query);
+        })
+        .await;
+        test_harness::advance(Duration::from_millis(20)).await;
+        assert_eq!(count.get(), 2_i32);
+    });
+}
+
+#[cfg(feature = "tracing")]
+type SpanFields = std::sync::Arc<std::sync::Mutex<Vec<(u64, &'static str, String)>>>;
+
+/// Records the fields of every span, as `(span, field, value)`
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct SpanRecorder {
+    spans: std::sync::Mutex<Vec<&'static str>>,
+    fields: SpanFields,
+}
+
+#[cfg(feature = "tracing")]
+impl SpanRecorder {
+    fn push(&self, id: u64, values: &tracing::span::Record<'_>) {
+        struct Visitor<'rec>(u64, &'rec mut Vec<(u64, &'static str, String)>);
+        impl tracing::field::Visit for Visitor<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                self.1.push((self.0, field.name(), format!("{value:?}")));
+            }
+        }
+
+        let mut fields = self.fields.lock().expect("lock shouldn't be poisoned");
+        values.record(&mut Visitor(id, &mut fields));
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut spans = self.spans.lock().expect("lock shouldn't be poisoned");
+        spans.push(span.metadata().name());
+        let id = spans.len() as u64;
+        drop(spans);
+        self.push(id, &tracing::span::Record::new(span.values()));
+        tracing::span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        self.push(span.into_u64(), values);
+    }
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, _: &tracing::Event<'_>) {}
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn fetch_span() {
+    test_harness::run_local(async {
+        let recorder = SpanRecorder::default();
+        let fields = std::sync::Arc::clone(&recorder.fields);
+        let _default = tracing::subscriber::set_default(recorder);
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+        client.fetch(&query).await;
+
+        let fields = fields.lock().expect("lock shouldn't be poisoned");
+        let field = |name| {
+            fields
+                .iter()
+                .find(|f| f.0 == 1 && f.1 == name)
+                .map(|f| f.2.clone())
+        };
+        assert_eq!(field("query"), Some(query.id().to_string()));
+        assert_eq!(field("attempt").as_deref(), Some("1"));
+        assert_eq!(field("outcome").as_deref(), Some("\"ok\""));
+    });
+}
+
+#[test]
+fn max_cached_queries() {
+    test_harness::run_local(async {
+        let client = QueryClient::new(
+            ClientOpts::with_cache_time(CacheTime::Infinite).set_max_cached_queries(2),
+        );
+        let new_query = |v: i32| Query::new(move || Box::pin(async move { Ok::<i32, ()>(v) }));
+        let active = new_query(1_i32);
+        let inactive = new_query(2_i32);
+        let newest = new_query(3_i32);
+
+        let _guard = client.subscribe_query(&active, |_| {});
+        client.fetch(&active).await;
+        client.fetch(&inactive).await;
+        client.fetch(&newest).await;
+
+        // `active` is the least recently used, but is kept as it's subscribed
+        assert!(client.query_data(&active).is_some());
+        assert!(client.query_data(&inactive).is_none());
+        assert!(client.query_data(&newest).is_some());
+    });
+}
+
+#[test]
+fn get_or_subscribe() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let listener = |seen: &Rc<RefCell<Vec<_>>>| {
+            let seen = Rc::clone(seen);
+            move |data: QueryData<i32, ()>| seen.borrow_mut().push(data)
+        };
+
+        let (data, guard) = client.get_or_subscribe(&query, listener(&seen));
+        assert!(data.is_none());
+        drop(guard);
+
+        client.fetch(&query).await;
+        let (data, _guard) = client.get_or_subscribe(&query, listener(&seen));
+        assert!(matches!(data, Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 1_i32));
+
+        // Every change after the snapshot reaches the listener
+        client.set_query_data(&query, 2_i32);
+        assert!(matches!(
+            *seen.borrow().as_slice(),
+            [QueryData::Ok(ref d, QueryStatus::Idle)] if **d == 2_i32
+        ));
+    });
+}
+
+#[test]
+fn prefetch_all() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let number = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let name = Query::new_with_param(|id: &i32| {
+            let id = *id;
+            Box::pin(async move { Ok::<String, ()>(format!("item {id}")) })
+        });
+
+        client
+            .prefetch_all()
+            .add(&number, ())
+            .add(&name, 2_i32)
+            .run()
+            .await;
+
+        assert!(matches!(client.query_data(&number), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+        assert!(matches!(client.query_data(&name), Some(QueryData::Ok(d, _)) if *d == "item 2"));
+    });
+}
+
+#[test]
+fn yield_after_fetches() {
+    fn order(opts: ClientOpts<'static>) -> Vec<&'static str> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        test_harness::run_local(async {
+            let client = QueryClient::new(opts);
+            let queries: Vec<_> = (0_i32..5_i32)
+                .map(|_| {
+                    let events = Rc::clone(&events);
+                    Query::new(move || {
+                        events.borrow_mut().push("fetch");
+                        Box::pin(async {
+                            time::sleep(Duration::from_millis(10)).await;
+                            Ok::<i32, ()>(1_i32)
+                        })
+                    })
+                })
+                .collect();
+            for query in &queries {
+                client.prefetch(query);
+            }
+            task::spawn_local({
+                let events = Rc::clone(&events);
+                async move { events.borrow_mut().push("tick") }
+            });
+
+            test_harness::advance(Duration::from_millis(20)).await;
+        });
+        events.take()
+    }
+
+    assert_eq!(
+        order(ClientOpts::new()),
+        ["fetch", "fetch", "fetch", "fetch", "fetch", "tick"]
+    );
+    // Fetches past the first 2 in progress let the other task run first
+    assert_eq!(
+        order(ClientOpts::new().set_yield_after_fetches(2)),
+        ["fetch", "fetch", "tick", "fetch", "fetch", "fetch"]
+    );
+}
+
+#[test]
+fn subscribe_many() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let number = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let name = Query::new_with_param(|id: &i32| {
+            let id = *id;
+            Box::pin(async move { Ok::<String, ()>(format!("item {id}")) })
+        });
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let guard = client
+            .subscribe_many()
+            .add(&number, {
+                let seen = Rc::clone(&seen);
+                move |_| seen.borrow_mut().push("number")
+            })
+            .add(&name, {
+                let seen = Rc::clone(&seen);
+                move |_| seen.borrow_mut().push("name")
+            })
+            .guard();
+        client.set_query_data(&number, 2_i32);
+        client.set_query_data(&name, "item 3".to_owned());
+        assert_eq!(*seen.borrow(), ["number", "name"]);
+
+        drop(guard);
+        assert_eq!(client.peek(&number).observer_count, 0);
+        assert_eq!(client.peek(&name).observer_count, 0);
+    });
+}
+
+#[test]
+fn active_query_ids() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let first = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let second = Query::new(|| Box::pin(async { Ok::<i32, ()>(2_i32) }));
+
+        let first_guard = client.subscribe_query(&first, |_| {});
+        let second_guard = client.subscribe_query(&second, |_| {});
+        assert_eq!(client.active_query_ids().len(), 2);
+
+        drop(first_guard);
+        assert_eq!(client.active_query_ids().len(), 1);
+        drop(second_guard);
+    });
+}
+
+#[test]
+fn persist_all() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }))
+            .persistable("count", |d| serde_json::to_string(d).ok());
+        let names = Query::new(|| Box::pin(async { Ok::<Vec<String>, ()>(vec!["a".to_owned()]) }))
+            .persistable("names", |d| serde_json::to_string(d).ok());
+        let failing = Query::new_with_opts(
+            || Box::pin(async { Err::<i32, ()>(()) }),
+            QueryOpts::with_retry(RetryConfig::none()),
+        )
+        .persistable("failing", |d| serde_json::to_string(d).ok());
+        let unpersisted = Query::new(|| Box::pin(async { Ok::<i32, ()>(3_i32) }));
+
+        client.fetch(&count).await;
+        client.fetch(&names).await;
+        client.fetch(&failing).await;
+        client.fetch(&unpersisted).await;
+
+        let persisted = client.persist_all();
+        assert_eq!(persisted.len(), 2);
+        let count: i32 =
+            serde_json::from_str(persisted.get("count").expect("count should be persisted"))
+                .expect("count should deserialize");
+        assert_eq!(count, 1_i32);
+        let names: Vec<String> =
+            serde_json::from_str(persisted.get("names").expect("names should be persisted"))
+                .expect("names should deserialize");
+        assert_eq!(names, ["a"]);
+    });
+}
+
+#[test]
+fn guard_leak() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let seen = Rc::new(Cell::new(0_i32));
+        let listener = |seen: &Rc<Cell<i32>>| {
+            let seen = Rc::clone(seen);
+            move |_| seen.set(seen.get() + 1_i32)
+        };
+
+        // Not held, so unsubscribes straight away
+        drop(client.subscribe_query(&query, listener(&seen)));
+        client.set_query_data(&query, 2_i32);
+        assert_eq!(seen.get(), 0_i32);
+
+        client.subscribe_query(&query, listener(&seen)).leak();
+        client.set_query_data(&query, 3_i32);
+        assert_eq!(seen.get(), 1_i32);
+        assert_eq!(client.peek(&query).observer_count, 1);
+
+        // Guards owned by the leaked guard are kept too
+        let other = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        client.subscribe_many().add(&other, |_| {}).guard().leak();
+        assert_eq!(client.peek(&other).observer_count, 1);
+
+        // As is a spawned mutation, which runs to completion
+        let mutation = Mutation::new(|v: &i32| {
+            let v = *v;
+            Box::pin(async move { Ok::<i32, ()>(v) })
+        });
+        client
+            .mutate_spawn::<_, _, _, ()>(&mutation, 2_i32, None)
+            .leak();
+        task::yield_now().await;
+        assert!(
+            matches!(client.mutation_data(&mutation), Some(MutationData::Ok(d)) if *d == 2_i32)
+        );
+    });
+}
+
+#[test]
+fn fetch_and_retain() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_opts(
+            || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+            QueryOpts::with_cache_time(CacheTime::Duration(Duration::from_secs(5))),
+        );
+
+        let (res, guard) = client.fetch_and_retain(&query, ()).await;
+        assert!(matches!(res, FetchResult::Fresh(Ok(d)) if *d == 1_i32));
+        test_harness::advance(Duration::from_secs(10)).await;
+        assert!(client.query_data(&query).is_some());
+
+        drop(guard);
+        test_harness::advance(Duration::from_secs(6)).await;
+        assert!(client.query_data(&query).is_none());
+    });
+}
+
+#[test]
+fn std_error_query() {
+    #[derive(Debug)]
+    struct NotFound;
+
+    impl fmt::Display for NotFound {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "not found")
+        }
+    }
+
+    impl std::error::Error for NotFound {}
+
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| Box::pin(async { Err::<i32, _>(StdError(NotFound)) }));
+
+        let res = client.fetch(&query).await;
+        let FetchResult::Fresh(Err(e)) = res else {
+            panic!("query should have errored");
+        };
+        assert_eq!(DisplayError(&*e).to_string(), "not found");
+    });
+}
+
+#[test]
+fn fetch_blocking() {
+    let client = QueryClient::default();
+    let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+    assert!(matches!(client.fetch_blocking(&query, ()), FetchResult::Fresh(Ok(d)) if *d == 1_i32));
+    assert!(
+        matches!(client.query_data(&query), Some(QueryData::Ok(d, QueryStatus::Idle)) if *d == 1_i32)
+    );
+}
+
+#[test]
+fn updated_at() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let fail = Rc::new(Cell::new(false));
+        let query = Query::new_with_opts(
+            {
+                let fail = Rc::clone(&fail);
+                move || {
+                    let fail = fail.get();
+                    Box::pin(async move {
+                        if fail {
+                            Err(())
+                        } else {
+                            Ok(1_i32)
+                        }
+                    })
+                }
+            },
+            QueryOpts::with_retry(RetryConfig::none()),
+        );
+
+        client.fetch(&query).await;
+        let peek = client.peek(&query);
+        let data_updated_at = peek.data_updated_at.expect("fetch should have succeeded");
+        assert!(peek.error_updated_at.is_none());
+
+        time::advance(Duration::from_secs(1)).await;
+        fail.set(true);
+        client.fetch(&query).await;
+        let peek = client.peek(&query);
+        assert_eq!(peek.data_updated_at, Some(data_updated_at));
+        let error_updated_at = peek.error_updated_at.expect("fetch should have failed");
+        assert_eq!(
+            error_updated_at.duration_since(data_updated_at),
+            Duration::from_secs(1)
+        );
+    });
+}
+
+#[test]
+fn clear_errors() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let fail = Rc::new(Cell::new(true));
+        let new_query = || {
+            let fail = Rc::clone(&fail);
+            Query::new_with_param_and_opts(
+                move |&arg: &i32| {
+                    let fail = fail.get();
+                    Box::pin(async move {
+                        if fail {
+                            Err(())
+                        } else {
+                            Ok(arg)
+                        }
+                    })
+                },
+                QueryOpts::with_retry(RetryConfig::none()),
+            )
+        };
+        let active = new_query();
+        let inactive = new_query();
+
+        let _guard = client.subscribe_query_with_arg(&active, 1_i32, |_| {});
+        client.fetch_with_arg(&active, 1_i32).await;
+        client.fetch_with_arg(&inactive, 2_i32).await;
+        assert!(matches!(
+            client.query_data(&active),
+            Some(QueryData::Err(..))
+        ));
+        assert!(matches!(
+            client.query_data(&inactive),
+            Some(QueryData::Err(..))
+        ));
+
+        fail.set(false);
+        client.clear_errors();
+        assert!(matches!(
+            client.query_data(&active),
+            Some(QueryData::Pending(_))
+        ));
+        assert!(client.query_data(&inactive).is_none());
+
+        task::yield_now().await;
+        assert!(matches!(client.query_data(&active), Some(QueryData::Ok(d, _)) if *d == 1_i32));
+
+        // Refetched with the arg it was fetched with, without having been subscribed with it
+        fail.set(true);
+        let plain = new_query();
+        let _plain_guard = client.subscribe_query(&plain, |_| {});
+        client.fetch_with_arg(&plain, 3_i32).await;
+        assert!(matches!(
+            client.query_data(&plain),
+            Some(QueryData::Err(..))
+        ));
+
+        fail.set(false);
+        client.clear_errors();
+        task::yield_now().await;
+        assert!(matches!(client.query_data(&plain), Some(QueryData::Ok(d, _)) if *d == 3_i32));
+    });
+}
+
+#[test]
+fn errors_not_cached() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let count = Rc::new(Cell::new(0_i32));
+        let query = Query::new_with_opts(
+            {
+                let count = Rc::clone(&count);
+                move || {
+                    count.set(count.get() + 1_i32);
+                    let count = count.get();
+                    Box::pin(async move {
+                        if count == 2_i32 {
+                            Err(())
+                        } else {
+                            Ok(count)
+                        }
+                    })
+                }
+            },
+            QueryOpts::with_retry(RetryConfig::none()).set_cache_errors(false),
+        );
+
+        assert!(matches!(
+            client.fetch(&query).await,
+            FetchResult::Fresh(Ok(ref d)) if **d == 1_i32
+        ));
+        assert!(matches!(
+            client.fetch(&query).await,
+            FetchResult::Fresh(Err(_))
+        ));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Ok(ref d, QueryStatus::Idle)) if **d == 1_i32
+        ));
+
+        assert!(matches!(
+            client.fetch(&query).await,
+            FetchResult::Fresh(Ok(ref d)) if **d == 3_i32
+        ));
+        assert_eq!(count.get(), 3_i32);
+    });
+}
+
+#[test]
+fn errors_not_cached_without_data() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_opts(
+            || Box::pin(async { Err::<i32, ()>(()) }),
+            QueryOpts::with_retry(RetryConfig::none()).set_cache_errors(false),
+        );
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |d| seen.borrow_mut().push(d)
+        });
+
+        assert!(matches!(
+            client.fetch(&query).await,
+            FetchResult::Fresh(Err(_))
+        ));
+        assert!(matches!(
+            client.query_data(&query),
+            Some(QueryData::Pending(PendingStatus::Idle))
+        ));
+        assert!(!client.peek(&query).is_fetching);
+        assert!(matches!(
+            seen.borrow().last(),
+            Some(QueryData::Pending(PendingStatus::Idle))
+        ));
+    });
+}
+
+#[test]
+fn stale_result_shared() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new(|| {
+            Box::pin(async {
+                time::sleep(Duration::from_millis(10)).await;
+                Ok::<i32, ()>(1_i32)
+            })
+        });
+
+        let fetch = client.fetch(&query);
+        tokio::pin!(fetch);
+        assert!(futures::poll!(fetch.as_mut()).is_pending());
+        drop(client.remove_query(&query));
+
+        let result = fetch.await;
+        assert!(matches!(result, FetchResult::Stale(_)));
+        // Same shape as a fresh result, so both can be handled together
+        match result {
+            FetchResult::Fresh(Ok(d)) | FetchResult::Stale(Ok(d)) => assert_eq!(*d, 1_i32),
+            _ => panic!("fetch should succeed"),
+        }
+    });
+}
+
+#[test]
+fn no_cache() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let query = Query::new_with_opts(
+            || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+            QueryOpts::new().set_no_cache(true),
+        );
+
+        assert!(matches!(
+            client.fetch(&query).await,
+            FetchResult::Fresh(Ok(ref d)) if **d == 1_i32
+        ));
+        assert!(matches!(
+            client.fetch_with_arg(&query, ()).await,
+            FetchResult::Fresh(Ok(ref d)) if **d == 1_i32
+        ));
+        assert!(client.query_data(&query).is_none());
+        assert!(client.active_query_ids().is_empty());
+
+        // Inherrited from a client that doesn't cache any queries by default, unless opted back in
+        let client =
+            QueryClient::new(ClientOpts::new().set_query(QueryOpts::new().set_no_cache(true)));
+        let inherrits = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let cached = inherrits.with_opts(QueryOpts::new().set_no_cache(false));
+        client.fetch(&inherrits).await;
+        client.fetch(&cached).await;
+        assert!(client.query_data(&inherrits).is_none());
+        assert!(client.query_data(&cached).is_some());
+    });
+}
+
+#[test]
+fn error_cleared_on_refetch() {
+    test_harness::run_local(async {
+        let client = QueryClient::default();
+        let fail = Rc::new(Cell::new(true));
+        let query = Query::new_with_opts(
+            {
+                let fail = Rc::clone(&fail);
+                move || {
+                    let fail = fail.get();
+                    Box::pin(async move {
+                        // Still fetching after its first poll, so the loading status is notified on its own
+                        task::yield_now().await;
+                        if fail {
+                            Err(())
+                        } else {
+                            Ok(1_i32)
+                        }
+                    })
+                }
+            },
+            QueryOpts::with_retry(RetryConfig::none()),
+        );
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let _guard = client.subscribe_query(&query, {
+            let seen = Rc::clone(&seen);
+            move |data| seen.borrow_mut().push(data)
+        });
+        client.fetch(&query).await;
+        fail.set(false);
+        client.fetch(&query).await;
+
+        let seen = seen.borrow();
+        assert!(matches!(
+            *seen.as_slice(),
+            [
+                QueryData::Err(_, QueryStatus::Idle),
+                QueryData::Err(_, QueryStatus::Loading),
+                QueryData::Ok(ref d, QueryStatus::Idle),
+            ] if **d == 1_i32
+        ));
+        let had_error: Vec<_> = seen.iter().map(QueryData::had_error).collect();
+        assert_eq!(had_error, [true, true, false]);
+    });
+}
@@ -53,6 +53,18 @@ impl<T> SetOption<T> {
     pub const fn set(value: T) -> Self {
         Self::Set(value)
     }
+
+    /// `other` if it's set, otherwise this option
+    // Possible drop, can't be const
+    #[allow(clippy::missing_const_for_fn)]
+    #[inline]
+    #[must_use = "Has no effect other than to return the merged option"]
+    pub fn merge(self, other: Self) -> Self {
+        match other {
+            Self::Set(_) => other,
+            Self::Inherrit => self,
+        }
+    }
 }
 
 /// Configuration for the length of time inactive queries/mutations remain cached
@@ -71,6 +83,12 @@ impl Default for CacheTime {
     }
 }
 
+impl From<Duration> for CacheTime {
+    fn from(value: Duration) -> Self {
+        Self::Duration(value)
+    }
+}
+
 impl ConstDefault for CacheTime {
     const DEFAULT: Self = Self::const_default();
 }
@@ -82,6 +100,20 @@ impl CacheTime {
     pub const fn const_default() -> Self {
         Self::Duration(Duration::from_secs(5 * 60))
     }
+
+    /// Remains in cache for `secs` seconds
+    #[must_use = "Creating a cache time has no effect"]
+    #[inline]
+    pub const fn secs(secs: u64) -> Self {
+        Self::Duration(Duration::from_secs(secs))
+    }
+
+    /// Remains in cache for `millis` milliseconds
+    #[must_use = "Creating a cache time has no effect"]
+    #[inline]
+    pub const fn millis(millis: u64) -> Self {
+        Self::Duration(Duration::from_millis(millis))
+    }
 }
 
 /// Setting for how [`QueryClient`] should handle being offline in the browser
@@ -119,3 +151,177 @@ impl NetworkMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{error::Error, retry::RetryConfig, CacheTime, NetworkMode, SetOption};
+    use crate::{client::ClientOpts, mutation::MutationOpts, query::QueryOpts};
+
+    const CACHE_TIME: CacheTime = CacheTime::Duration(Duration::from_secs(1));
+
+    #[test]
+    fn query_opts_from() {
+        let opts = QueryOpts::<()>::from(CACHE_TIME);
+        assert!(
+            matches!(opts.cache_time, SetOption::Set(CacheTime::Duration(d)) if d == Duration::from_secs(1))
+        );
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+
+        let opts = QueryOpts::<()>::from(NetworkMode::Always);
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+
+        let opts = QueryOpts::<()>::from(RetryConfig::none());
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Set(_)));
+    }
+
+    #[test]
+    fn cache_time_constructors() {
+        let duration = |cache_time| match cache_time {
+            CacheTime::Duration(d) => Some(d),
+            CacheTime::Infinite => None,
+        };
+        assert_eq!(duration(CacheTime::secs(2)), Some(Duration::from_secs(2)));
+        assert_eq!(
+            duration(CacheTime::millis(1500)),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            duration(Duration::from_secs(3).into()),
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn set_option_merge() {
+        let merge = |base, other| match SetOption::merge(base, other) {
+            SetOption::Set(n) => Some(n),
+            SetOption::Inherrit => None,
+        };
+        assert_eq!(merge(SetOption::Inherrit, SetOption::Inherrit), None);
+        assert_eq!(
+            merge(SetOption::Set(1_i32), SetOption::Inherrit),
+            Some(1_i32)
+        );
+        assert_eq!(
+            merge(SetOption::Inherrit, SetOption::Set(2_i32)),
+            Some(2_i32)
+        );
+        assert_eq!(
+            merge(SetOption::Set(1_i32), SetOption::Set(2_i32)),
+            Some(2_i32)
+        );
+    }
+
+    #[test]
+    fn opts_merge() {
+        let opts = QueryOpts::<()>::with_cache_time(CACHE_TIME)
+            .set_retry(RetryConfig::none())
+            .merge(
+                QueryOpts::with_network_mode(NetworkMode::Always)
+                    .set_cache_time(CacheTime::Infinite),
+            );
+        assert!(matches!(
+            opts.cache_time,
+            SetOption::Set(CacheTime::Infinite)
+        ));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert!(matches!(opts.retry, SetOption::Set(_)));
+
+        let opts = MutationOpts::<()>::with_cache_time(CACHE_TIME)
+            .merge(MutationOpts::with_network_mode(NetworkMode::Always));
+        assert!(matches!(
+            opts.cache_time,
+            SetOption::Set(CacheTime::Duration(_))
+        ));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+
+        let opts = ClientOpts::with_cache_time(CACHE_TIME)
+            .set_max_cached_queries(2)
+            .merge(
+                ClientOpts::with_network_mode(NetworkMode::Always).set_query(CacheTime::Infinite),
+            );
+        assert!(matches!(
+            opts.cache_time,
+            SetOption::Set(CacheTime::Duration(_))
+        ));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert_eq!(opts.max_cached_queries, Some(2));
+        assert!(matches!(
+            opts.query,
+            Some(QueryOpts {
+                cache_time: SetOption::Set(CacheTime::Infinite),
+                ..
+            })
+        ));
+        assert!(opts.mutation.is_none());
+    }
+
+    #[test]
+    fn mutation_opts_from() {
+        let opts = MutationOpts::<()>::from(CACHE_TIME);
+        assert!(
+            matches!(opts.cache_time, SetOption::Set(CacheTime::Duration(d)) if d == Duration::from_secs(1))
+        );
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+
+        let opts = MutationOpts::<()>::from(NetworkMode::Always);
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+
+        let opts = MutationOpts::<()>::from(RetryConfig::none());
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Set(_)));
+    }
+
+    #[test]
+    fn client_opts_from() {
+        let opts = ClientOpts::from(CACHE_TIME);
+        assert!(
+            matches!(opts.cache_time, SetOption::Set(CacheTime::Duration(d)) if d == Duration::from_secs(1))
+        );
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+        assert!(opts.query.is_none() && opts.mutation.is_none());
+
+        let opts = ClientOpts::from(NetworkMode::Always);
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(
+            opts.network_mode,
+            SetOption::Set(NetworkMode::Always)
+        ));
+        assert!(matches!(opts.retry, SetOption::Inherrit));
+        assert!(opts.query.is_none() && opts.mutation.is_none());
+
+        let opts = ClientOpts::from(RetryConfig::<dyn Error>::none());
+        assert!(matches!(opts.cache_time, SetOption::Inherrit));
+        assert!(matches!(opts.network_mode, SetOption::Inherrit));
+        assert!(matches!(opts.retry, SetOption::Set(_)));
+        assert!(opts.query.is_none() && opts.mutation.is_none());
+    }
+}
@@ -1,10 +1,12 @@
 use std::{
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
     rc::Rc,
 };
 
 use downcast_rs::{impl_downcast, Downcast};
 
+use super::retry::RetryAfter;
+
 pub trait ErrorDisplay {
     fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result;
 }
@@ -23,7 +25,82 @@ impl ErrorDisplay for () {
 
 pub trait Error: Debug + ErrorDisplay {
     fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>>;
+
+    /// This error as a [`RetryAfter`], if it can carry a delay to wait before retrying
+    /// Return `Some(self)` for errors implementing [`RetryAfter`], so their delay is preferred over the configured one
+    /// Default: `None`
+    fn as_retry_after(&self) -> Option<&dyn RetryAfter> {
+        None
+    }
 }
 
 pub trait ErrorKind: Downcast {}
 impl_downcast!(ErrorKind);
+
+/// Wraps any [`std::error::Error`] so it can be used as a query or mutation error without implementing [`Error`]
+/// Formats with its [`Display`] impl, and has no [`ErrorKind`]
+// A blanket impl over `std::error::Error` would conflict with the impl for `()`, as std could implement it for `()` in the future
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdError<E>(pub E);
+
+impl<E: std::error::Error> From<E> for StdError<E> {
+    #[inline]
+    fn from(value: E) -> Self {
+        Self(value)
+    }
+}
+
+impl<E: std::error::Error> ErrorDisplay for StdError<E> {
+    fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<E: std::error::Error> Error for StdError<E> {
+    fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>> {
+        None
+    }
+}
+
+/// Renders an [`Error`] with [`ErrorDisplay::err_fmt`] through [`Display`]
+/// For use in error UIs, such as `format!("{}", DisplayError(&*err))`
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayError<'err, E: Error + ?Sized>(pub &'err E);
+
+impl<E: Error + ?Sized> Display for DisplayError<'_, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.err_fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt::{self, Formatter},
+        rc::Rc,
+    };
+
+    use super::{DisplayError, Error, ErrorDisplay, ErrorKind};
+
+    #[derive(Debug)]
+    struct NotFound(u32);
+
+    impl ErrorDisplay for NotFound {
+        fn err_fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "item {} not found", self.0)
+        }
+    }
+
+    impl Error for NotFound {
+        fn kind(self: Rc<Self>) -> Option<Box<dyn ErrorKind>> {
+            None
+        }
+    }
+
+    #[test]
+    fn display_error() {
+        let err = Rc::new(NotFound(3));
+        assert_eq!(format!("{}", DisplayError(&*err)), "item 3 not found");
+        assert_eq!(DisplayError(&()).to_string(), "no error type");
+    }
+}
@@ -72,12 +72,112 @@ pub(crate) enum RetryType<'cfg, 'func, E> {
 }
 
 impl<'func, E: Error + 'func> RetryType<'_, 'func, E> {
-    pub(crate) fn retry_delay(&self, failure_count: u32, error: Rc<E>) -> Option<Duration> {
+    pub(crate) fn retry_delay(
+        &self,
+        failure_count: u32,
+        error: Rc<E>,
+        elapsed: Duration,
+    ) -> Option<Duration> {
         match *self {
-            Self::Concrete(ref c) => c.retry_delay(failure_count, error),
-            Self::TraitObject(ref t) => t.retry_delay(failure_count, error),
+            Self::Concrete(ref c) => c.retry_delay(failure_count, error, elapsed),
+            Self::TraitObject(ref t) => t.retry_delay(failure_count, error, elapsed),
         }
     }
+
+    pub(crate) fn notify_retry(&self, failure_count: u32, error: Rc<E>) {
+        match *self {
+            Self::Concrete(ref c) => c.notify_retry(failure_count, error),
+            Self::TraitObject(ref t) => t.notify_retry(failure_count, error),
+        }
+    }
+}
+
+/// Client level [`CacheTime`] & [`NetworkMode`], resolved once for each [`ActionType`]
+/// Only an action's own overrides need checking when resolving for it, without going through [`ConfigOpt`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResolvedOpts {
+    query: ResolvedDefaults,
+    mutation: ResolvedDefaults,
+    /// Only queries cache errors, so this isn't part of [`ResolvedDefaults`]
+    cache_errors: bool,
+    /// Only set by the client's default query options, see [`QueryOpts.no_cache`]
+    no_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ResolvedDefaults {
+    cache_time: CacheTime,
+    network_mode: NetworkMode,
+}
+
+impl ResolvedDefaults {
+    fn new(client: &ClientOpts<'_>, action: &impl ClientAction) -> Self {
+        Self {
+            cache_time: resolve_option(ConfigOption::CacheTime, client, action),
+            network_mode: resolve_option(ConfigOption::NetworkMode, client, action),
+        }
+    }
+}
+
+impl ResolvedOpts {
+    pub(crate) fn new(client: &ClientOpts<'_>) -> Self {
+        Self {
+            query: ResolvedDefaults::new(client, &QueryOpts::<()>::new()),
+            mutation: ResolvedDefaults::new(client, &MutationOpts::<()>::new()),
+            cache_errors: resolve_cache_errors(client),
+            no_cache: matches!(
+                client.query.as_ref().map(|q| q.no_cache),
+                Some(SetOption::Set(true))
+            ),
+        }
+    }
+
+    const fn defaults(&self, action_type: ActionType) -> &ResolvedDefaults {
+        match action_type {
+            ActionType::Query => &self.query,
+            ActionType::Mutation => &self.mutation,
+        }
+    }
+
+    pub(crate) fn cache_time(&self, action: &impl ClientAction) -> CacheTime {
+        match action.cache_time() {
+            SetOption::Set(cache_time) => cache_time,
+            SetOption::Inherrit => self.defaults(action.action_type()).cache_time,
+        }
+    }
+
+    pub(crate) fn network_mode(&self, action: &impl ClientAction) -> NetworkMode {
+        match action.network_mode() {
+            SetOption::Set(network_mode) => network_mode,
+            SetOption::Inherrit => self.defaults(action.action_type()).network_mode,
+        }
+    }
+
+    pub(crate) const fn cache_errors<E: ?Sized>(&self, query: &QueryOpts<'_, E>) -> bool {
+        match query.cache_errors {
+            SetOption::Set(cache_errors) => cache_errors,
+            SetOption::Inherrit => self.cache_errors,
+        }
+    }
+
+    pub(crate) const fn no_cache<E: ?Sized>(&self, query: &QueryOpts<'_, E>) -> bool {
+        match query.no_cache {
+            SetOption::Set(no_cache) => no_cache,
+            SetOption::Inherrit => self.no_cache,
+        }
+    }
+}
+
+/// Client level [`QueryOpts.cache_errors`], caching errors if neither the client or its default query options set it
+fn resolve_cache_errors(client: &ClientOpts<'_>) -> bool {
+    let client_query = client
+        .query
+        .as_ref()
+        .map_or(SetOption::Inherrit, |q| q.cache_errors);
+    match client.cache_errors.merge(client_query) {
+        SetOption::Set(cache_errors) => cache_errors,
+        SetOption::Inherrit => true,
+    }
 }
 
 pub(crate) fn resolve_retry<'client, 'query, 'res, 'func, E>(
@@ -125,6 +225,7 @@ pub(crate) trait GetOption {
     fn get(&self, opt: ConfigOption) -> Option<&(dyn ConfigOpt)>;
 }
 
+#[derive(Clone, Copy)]
 pub(crate) enum ActionType {
     Query,
     Mutation,
@@ -132,18 +233,36 @@ pub(crate) enum ActionType {
 
 pub(crate) trait ClientAction: GetOption {
     fn action_type(&self) -> ActionType;
+    fn cache_time(&self) -> SetOption<CacheTime>;
+    fn network_mode(&self) -> SetOption<NetworkMode>;
 }
 
 impl<E: ?Sized> ClientAction for QueryOpts<'_, E> {
     fn action_type(&self) -> ActionType {
         ActionType::Query
     }
+
+    fn cache_time(&self) -> SetOption<CacheTime> {
+        self.cache_time
+    }
+
+    fn network_mode(&self) -> SetOption<NetworkMode> {
+        self.network_mode
+    }
 }
 
 impl<E: ?Sized> ClientAction for MutationOpts<'_, E> {
     fn action_type(&self) -> ActionType {
         ActionType::Mutation
     }
+
+    fn cache_time(&self) -> SetOption<CacheTime> {
+        self.cache_time
+    }
+
+    fn network_mode(&self) -> SetOption<NetworkMode> {
+        self.network_mode
+    }
 }
 
 impl GetOption for ClientOpts<'_> {
@@ -172,3 +291,69 @@ impl<E: ?Sized> GetOption for MutationOpts<'_, E> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ResolvedOpts;
+    use crate::{
+        client::ClientOpts,
+        config::{CacheTime, NetworkMode},
+        mutation::MutationOpts,
+        query::QueryOpts,
+    };
+
+    fn secs(cache_time: CacheTime) -> Option<u64> {
+        match cache_time {
+            CacheTime::Duration(d) => Some(d.as_secs()),
+            CacheTime::Infinite => None,
+        }
+    }
+
+    #[test]
+    fn resolved_respects_overrides() {
+        let client = ClientOpts::new()
+            .set_cache_time(CacheTime::Duration(Duration::from_secs(1)))
+            .set_network_mode(NetworkMode::Always)
+            .set_query(QueryOpts::with_cache_time(CacheTime::Duration(
+                Duration::from_secs(2),
+            )));
+        let resolved = ResolvedOpts::new(&client);
+
+        assert_eq!(secs(resolved.cache_time(&QueryOpts::<()>::new())), Some(2));
+        assert_eq!(
+            secs(resolved.cache_time(&MutationOpts::<()>::new())),
+            Some(1)
+        );
+        assert_eq!(
+            secs(resolved.cache_time(&QueryOpts::<()>::with_cache_time(CacheTime::Infinite))),
+            None
+        );
+        assert!(matches!(
+            resolved.network_mode(&QueryOpts::<()>::new()),
+            NetworkMode::Always
+        ));
+        assert!(matches!(
+            resolved.network_mode(&MutationOpts::<()>::with_network_mode(
+                NetworkMode::OfflineFirst
+            )),
+            NetworkMode::OfflineFirst
+        ));
+    }
+
+    #[test]
+    fn resolved_cache_errors() {
+        let resolved = ResolvedOpts::new(&ClientOpts::new());
+        assert!(resolved.cache_errors(&QueryOpts::<()>::new()));
+        let resolved = ResolvedOpts::new(&ClientOpts::default());
+        assert!(resolved.cache_errors(&QueryOpts::<()>::new()));
+
+        let client = ClientOpts::new()
+            .set_cache_errors(true)
+            .set_query(QueryOpts::new().set_cache_errors(false));
+        let resolved = ResolvedOpts::new(&client);
+        assert!(!resolved.cache_errors(&QueryOpts::<()>::new()));
+        assert!(resolved.cache_errors(&QueryOpts::<()>::new().set_cache_errors(true)));
+    }
+}
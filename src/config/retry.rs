@@ -4,10 +4,12 @@ use std::{
     time::Duration,
 };
 
-use crate::const_default::ConstDefault;
+use super::error::{Error, ErrorKind};
+use crate::{const_default::ConstDefault, random::random};
 
 type DelayFn<'func, E> = Rc<dyn Fn(u32, Rc<E>) -> Duration + 'func>;
 type RetryFn<'func, E> = Rc<dyn Fn(u32, Rc<E>) -> bool + 'func>;
+type ObserverFn<'func, E> = Rc<dyn Fn(u32, Rc<E>) + 'func>;
 
 // Already small as possible
 #[allow(variant_size_differences)]
@@ -59,6 +61,14 @@ impl<E: ?Sized> RetryPolicy<'_, E> {
     pub const fn const_default() -> Self {
         Self::Num(3)
     }
+
+    fn should_retry(&self, failure_count: u32, error: Rc<E>) -> bool {
+        match *self {
+            Self::Func(ref func) => func(failure_count, error),
+            Self::Infinite => true,
+            Self::Num(n) => failure_count <= n,
+        }
+    }
 }
 
 /// Control how long between retries
@@ -74,6 +84,24 @@ pub enum RetryDelay<'func, E: ?Sized> {
     /// Always wait a set time between retries
     Always(Duration),
     /// Retry after the time returned from the closure, given the failure count and error
+    ///
+    /// # Example
+    /// Waiting for as long as the server asked, falling back to a second per failure
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rust_query::config::retry::RetryConfig;
+    ///
+    /// struct HttpError {
+    ///     retry_after: Option<Duration>,
+    /// }
+    ///
+    /// let retry = RetryConfig::<HttpError>::default().delay_fn(|failure_count, error| {
+    ///     error
+    ///         .retry_after
+    ///         .unwrap_or_else(|| Duration::from_secs(failure_count.into()))
+    /// });
+    /// ```
     DelayFn(DelayFn<'func, E>),
 }
 
@@ -126,13 +154,49 @@ impl<E: ?Sized> RetryDelay<'_, E> {
     }
 }
 
+/// An error that may carry a delay to wait before retrying, such as from a `Retry-After` header
+/// Errors also need to return themselves from [`Error::as_retry_after`] for the delay to be used
+pub trait RetryAfter {
+    /// How long to wait before retrying, or `None` to use the configured [`RetryDelay`]
+    fn retry_after(&self) -> Option<Duration>;
+}
+
+/// Get the delay `error` asks to be retried after, if it has one
+/// Retries already prefer this over the configured [`RetryDelay`]
+#[must_use = "Has no effect other than to get the delay"]
+#[inline]
+pub fn retry_after<E: Error + ?Sized>(error: &E) -> Option<Duration> {
+    error.as_retry_after().and_then(RetryAfter::retry_after)
+}
+
 /// Configuration for how queries and mutations are retired
-#[derive(Debug)]
 pub struct RetryConfig<'func, E: ?Sized> {
     /// See [`RetryPolicy`]
     pub policy: RetryPolicy<'func, E>,
     /// See [`RetryDelay`]
     pub delay: RetryDelay<'func, E>,
+    /// Stop retrying once a retry would start more than this long after the first attempt
+    /// Default: no limit
+    pub max_elapsed: Option<Duration>,
+    /// Called before waiting to retry, given the failure count and error
+    /// Default: none
+    pub on_retry: Option<ObserverFn<'func, E>>,
+    /// Randomly vary each delay from the [`RetryDelay`] by up to this fraction of it either way, so failed fetches don't all retry together
+    /// Not applied to delays from [`retry_after`]
+    /// Default: none
+    pub jitter: Option<f64>,
+}
+
+impl<E: ?Sized> Debug for RetryConfig<'_, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryConfig")
+            .field("policy", &self.policy)
+            .field("delay", &self.delay)
+            .field("max_elapsed", &self.max_elapsed)
+            .field("on_retry", &self.on_retry.as_ref().map(|_| ".."))
+            .field("jitter", &self.jitter)
+            .finish()
+    }
 }
 
 impl<E: ?Sized> Default for RetryConfig<'_, E> {
@@ -140,6 +204,9 @@ impl<E: ?Sized> Default for RetryConfig<'_, E> {
         Self {
             policy: RetryPolicy::default(),
             delay: RetryDelay::default(),
+            max_elapsed: None,
+            on_retry: None,
+            jitter: None,
         }
     }
 }
@@ -149,6 +216,9 @@ impl<E: ?Sized> Clone for RetryConfig<'_, E> {
         Self {
             policy: self.policy.clone(),
             delay: self.delay.clone(),
+            max_elapsed: self.max_elapsed,
+            on_retry: self.on_retry.clone(),
+            jitter: self.jitter,
         }
     }
 }
@@ -165,6 +235,9 @@ impl<'func, E: ?Sized> RetryConfig<'func, E> {
         Self {
             policy: RetryPolicy::const_default(),
             delay: RetryDelay::const_default(),
+            max_elapsed: None,
+            on_retry: None,
+            jitter: None,
         }
     }
 
@@ -175,6 +248,9 @@ impl<'func, E: ?Sized> RetryConfig<'func, E> {
         Self {
             policy: RetryPolicy::Num(0),
             delay: RetryDelay::default(),
+            max_elapsed: None,
+            on_retry: None,
+            jitter: None,
         }
     }
 
@@ -228,6 +304,16 @@ impl<'func, E: ?Sized> RetryConfig<'func, E> {
         self
     }
 
+    /// Stop retrying once a retry would start more than `duration` after the first attempt
+    // Possible drop, can't be const
+    #[allow(clippy::missing_const_for_fn)]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub fn max_elapsed(mut self, duration: Duration) -> Self {
+        self.max_elapsed = Some(duration);
+        self
+    }
+
     /// Set the retry delay to use the provided closure
     // Possible drop, can't be const
     #[allow(clippy::missing_const_for_fn)]
@@ -237,26 +323,140 @@ impl<'func, E: ?Sized> RetryConfig<'func, E> {
         self.delay = RetryDelay::DelayFn(Rc::new(func));
         self
     }
+
+    /// Randomly vary each retry delay by up to `fraction` of it either way, whichever [`RetryDelay`] is used
+    /// `fraction` is clamped to `0.0..=1.0`, a NaN `fraction` means no jitter
+    // Possible drop, can't be const
+    #[allow(clippy::missing_const_for_fn)]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.jitter = (!fraction.is_nan()).then(|| fraction.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Call the provided closure before waiting to retry, given the failure count and error
+    // Possible drop, can't be const
+    #[allow(clippy::missing_const_for_fn)]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub fn on_retry(mut self, func: impl Fn(u32, Rc<E>) + 'func) -> Self {
+        self.on_retry = Some(Rc::new(func));
+        self
+    }
 }
 
-impl<E: ?Sized> RetryConfig<'_, E> {
-    pub(crate) fn retry_delay(&self, failure_count: u32, error: Rc<E>) -> Option<Duration> {
-        match self.policy {
-            RetryPolicy::Func(ref func) if func(failure_count, Rc::clone(&error)) => Some(()),
-            RetryPolicy::Infinite => Some(()),
-            RetryPolicy::Num(ref n) if failure_count <= *n => Some(()),
-            _ => None,
-        }?;
-
-        Some(match self.delay {
-            RetryDelay::Always(ref d) => *d,
-            RetryDelay::Backoff {
-                ref initial,
-                ref maximum,
-            } => initial
-                .saturating_mul(2_u32.pow(failure_count.saturating_sub(1)))
-                .min(*maximum),
-            RetryDelay::DelayFn(ref func) => func(failure_count, error),
+impl<'func, E: Error + ?Sized + 'func> RetryConfig<'func, E> {
+    /// Only retry errors with an [`ErrorKind`] of `K` that `should_retry` returns true for, on top of the current policy
+    /// Errors without a kind, or with a kind of a different type, aren't retried
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub fn retry_on_kind<K: ErrorKind>(self, should_retry: impl Fn(&K) -> bool + 'func) -> Self {
+        let policy = self.policy.clone();
+        self.policy_fn(move |failure_count, error| {
+            let retry_kind = Rc::clone(&error)
+                .kind()
+                .and_then(|kind| kind.downcast_ref::<K>().map(&should_retry))
+                .unwrap_or(false);
+            retry_kind && policy.should_retry(failure_count, error)
         })
     }
+
+    /// The delay before retrying, preferring the error's [`retry_after`] over the configured [`RetryDelay`]
+    pub(crate) fn retry_delay(
+        &self,
+        failure_count: u32,
+        error: Rc<E>,
+        elapsed: Duration,
+    ) -> Option<Duration> {
+        if !self.policy.should_retry(failure_count, Rc::clone(&error)) {
+            return None;
+        }
+
+        let delay = retry_after(&*error).unwrap_or_else(|| {
+            let delay = match self.delay {
+                RetryDelay::Always(ref d) => *d,
+                RetryDelay::Backoff {
+                    ref initial,
+                    ref maximum,
+                } => initial
+                    .saturating_mul(2_u32.pow(failure_count.saturating_sub(1)))
+                    .min(*maximum),
+                RetryDelay::DelayFn(ref func) => func(failure_count, error),
+            };
+            self.jitter.map_or(delay, |jitter| {
+                let factor = random().mul_add(2.0, -1.0).mul_add(jitter, 1.0);
+                // Saturates rather than panicking like `Duration::mul_f64` when jittering up from near `Duration::MAX`
+                Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+            })
+        });
+
+        match self.max_elapsed {
+            Some(max) if elapsed.saturating_add(delay) > max => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+impl<E: ?Sized> RetryConfig<'_, E> {
+    pub(crate) fn notify_retry(&self, failure_count: u32, error: Rc<E>) {
+        if let Some(ref on_retry) = self.on_retry {
+            on_retry(failure_count, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::RetryConfig;
+
+    #[test]
+    fn jitter_within_band() {
+        let retry = RetryConfig::<()>::default()
+            .always(Duration::from_secs(1))
+            .with_jitter(0.5);
+        let delays: Vec<_> = (0_i32..20_i32)
+            .map(|_| {
+                retry
+                    .retry_delay(1, Rc::new(()), Duration::ZERO)
+                    .expect("should retry")
+            })
+            .collect();
+
+        assert!(delays
+            .iter()
+            .all(|d| (Duration::from_millis(500)..=Duration::from_millis(1500)).contains(d)));
+        assert!(delays.windows(2).any(|w| matches!(*w, [a, b] if a != b)));
+    }
+
+    #[test]
+    fn jitter_saturates() {
+        let retry = RetryConfig::<()>::default()
+            .always(Duration::MAX)
+            .with_jitter(1.0);
+        for _ in 0_i32..20_i32 {
+            assert!(retry.retry_delay(1, Rc::new(()), Duration::ZERO).is_some());
+        }
+
+        let retry = RetryConfig::<()>::default()
+            .backoff(Duration::MAX / 2, Duration::MAX)
+            .with_jitter(0.5);
+        for _ in 0_i32..20_i32 {
+            assert!(retry.retry_delay(3, Rc::new(()), Duration::ZERO).is_some());
+        }
+    }
+
+    #[test]
+    fn nan_jitter() {
+        let retry = RetryConfig::<()>::default()
+            .always(Duration::from_secs(1))
+            .with_jitter(f64::NAN);
+        assert!(retry.jitter.is_none());
+        assert_eq!(
+            retry.retry_delay(1, Rc::new(()), Duration::ZERO),
+            Some(Duration::from_secs(1))
+        );
+    }
 }
@@ -10,3 +10,16 @@ pub(crate) fn spawn_local<T: 'static>(f: impl Future<Output = T> + 'static) {
         drop(f.await);
     });
 }
+
+/// Let other tasks run before continuing, by yielding to the runtime, or waiting on a microtask in the browser
+pub(crate) async fn yield_now() {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::task::yield_now().await;
+    #[cfg(target_arch = "wasm32")]
+    drop(
+        wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(
+            &wasm_bindgen::JsValue::UNDEFINED,
+        ))
+        .await,
+    );
+}
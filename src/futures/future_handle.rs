@@ -1,37 +1,108 @@
-use std::{future::Future, pin::Pin, sync::Arc};
+use std::{
+    any::Any, cell::RefCell, future::Future, mem, panic::AssertUnwindSafe, pin::Pin, rc::Rc,
+};
 
-use futures::{future, stream::AbortHandle};
-use tokio::sync::Mutex;
+use futures::{future, stream::AbortHandle, FutureExt};
+
+/// When a cleanup closure should be run
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CleanupOn {
+    /// The future ran to completion
+    Completion,
+    /// The future was aborted before it could complete
+    Abort,
+}
+
+impl CleanupOn {
+    const fn matches(self, aborted: bool) -> bool {
+        match self {
+            Self::Completion => !aborted,
+            Self::Abort => aborted,
+        }
+    }
+}
 
 struct CleanupState<'handle> {
     done: bool,
-    cleanup: Vec<Box<dyn Fn() + 'handle>>,
+    aborted: bool,
+    cleanup: Vec<(CleanupOn, Box<dyn Fn() + 'handle>)>,
+}
+
+type SharedCleanup<'handle> = Rc<RefCell<CleanupState<'handle>>>;
+
+impl CleanupState<'_> {
+    /// Runs the cleanup for finishing the way described by `aborted`, unless it has already finished
+    /// Not borrowed while running cleanup, so cleanup can add more, which runs straight away
+    fn finish(state: &RefCell<Self>, aborted: bool) {
+        let cleanup = {
+            let mut state = state.borrow_mut();
+            if state.done {
+                return;
+            }
+            state.done = true;
+            state.aborted = aborted;
+            mem::take(&mut state.cleanup)
+        };
+        for (on, f) in cleanup {
+            if on.matches(aborted) {
+                f();
+            }
+        }
+    }
 }
 
 pub(crate) struct CleanupHandle<'handle> {
-    inner: Arc<Mutex<CleanupState<'handle>>>,
+    inner: SharedCleanup<'handle>,
+    handle: AbortHandle,
 }
 
 pub(crate) struct FutureHandle<'handle> {
     handle: AbortHandle,
-    cleanup: Arc<Mutex<CleanupState<'handle>>>,
+    cleanup: SharedCleanup<'handle>,
+}
+
+/// Aborts the future when dropped, like dropping its [`FutureHandle`], for anything else that should be able to end it
+pub(crate) struct AbortOnDrop<'handle> {
+    handle: AbortHandle,
+    cleanup: SharedCleanup<'handle>,
 }
 
 impl<'handle> FutureHandle<'handle> {
     pub(crate) fn cleanup(&self) -> CleanupHandle<'handle> {
         CleanupHandle {
-            inner: Arc::clone(&self.cleanup),
+            inner: Rc::clone(&self.cleanup),
+            handle: self.handle.clone(),
         }
     }
 }
 
 impl<'handle> CleanupHandle<'handle> {
-    pub(crate) async fn add_cleanup(self, f: impl Fn() + 'handle) {
-        let mut cleanup = self.inner.lock().await;
-        if cleanup.done {
+    pub(crate) fn abort_on_drop(&self) -> AbortOnDrop<'handle> {
+        AbortOnDrop {
+            handle: self.handle.clone(),
+            cleanup: Rc::clone(&self.inner),
+        }
+    }
+
+    /// Add cleanup that runs once the future has completed
+    pub(crate) fn add_cleanup(&self, f: impl Fn() + 'handle) {
+        self.add_cleanup_on(CleanupOn::Completion, f);
+    }
+
+    /// Add cleanup that runs when the future finishes in the way described by `on`
+    /// Runs immediately if the future has already finished that way, so it can be added from within other cleanup
+    pub(crate) fn add_cleanup_on(&self, on: CleanupOn, f: impl Fn() + 'handle) {
+        let mut cleanup = self.inner.borrow_mut();
+        if !cleanup.done {
+            cleanup.cleanup.push((on, Box::new(f)));
+            return;
+        }
+
+        let run = on.matches(cleanup.aborted);
+        // Released first, so `f` can add more cleanup
+        drop(cleanup);
+        if run {
             f();
-        } else {
-            cleanup.cleanup.push(Box::new(f));
         }
     }
 }
@@ -39,34 +110,159 @@ impl<'handle> CleanupHandle<'handle> {
 impl Drop for FutureHandle<'_> {
     fn drop(&mut self) {
         self.handle.abort();
+        CleanupState::finish(&self.cleanup, true);
     }
 }
 
+impl Drop for AbortOnDrop<'_> {
+    fn drop(&mut self) {
+        self.handle.abort();
+        CleanupState::finish(&self.cleanup, true);
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("..")
+}
+
 pub(crate) fn spawn_local_handle<'handle>(
     f: impl Future<Output = ()> + 'handle,
 ) -> FutureHandle<'handle> {
-    let cleanup = Arc::new(Mutex::new(CleanupState {
+    let cleanup = Rc::new(RefCell::new(CleanupState {
         done: false,
+        aborted: false,
         cleanup: Vec::new(),
     }));
     let boxed: Pin<Box<dyn Future<Output = ()>>> = Box::pin({
-        let cleanup = Arc::clone(&cleanup);
+        let cleanup = Rc::clone(&cleanup);
         async move {
-            f.await;
-
-            let mut cleanup = cleanup.lock().await;
-            cleanup.done = true;
-            for f in &cleanup.cleanup {
-                f();
+            // Cleanup must still run if the future panics, the panic is logged instead of unwinding further
+            if let Err(panic) = AssertUnwindSafe(f).catch_unwind().await {
+                log::error!("spawned future panicked: {}", panic_message(&*panic));
             }
+
+            CleanupState::finish(&cleanup, false);
         }
     });
     let extended: Pin<Box<dyn Future<Output = ()> + 'static>> =
     // SAFETY: We are just transmuting the lifetime here so that we can spawn the future.
     // This is safe because we wrap the future in an `Abortable` future which will be
     // immediately aborted once the reactive scope is dropped.
-        unsafe { std::mem::transmute(boxed) };
+        unsafe { mem::transmute(boxed) };
     let (abortable, handle) = future::abortable(extended);
     super::spawn_local(abortable);
     FutureHandle { handle, cleanup }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use tokio::task;
+
+    use futures::future;
+
+    use super::{spawn_local_handle, CleanupOn, FutureHandle};
+    use crate::test_harness;
+
+    #[test]
+    fn cleanup_after_panic() {
+        test_harness::run_local(async {
+            let cleaned = Rc::new(Cell::new(false));
+            let handle = spawn_local_handle(async { panic!("query function panicked") });
+            handle.cleanup().add_cleanup({
+                let cleaned = Rc::clone(&cleaned);
+                move || cleaned.set(true)
+            });
+
+            for _ in 0_i32..10_i32 {
+                task::yield_now().await;
+            }
+            assert!(cleaned.get());
+            drop(handle);
+        });
+    }
+
+    #[test]
+    fn cleanup_on_completion_and_abort() {
+        fn add_all(handle: &FutureHandle<'_>, ran: &Rc<Cell<[bool; 2]>>) {
+            for (i, on) in [CleanupOn::Completion, CleanupOn::Abort]
+                .into_iter()
+                .enumerate()
+            {
+                let ran = Rc::clone(ran);
+                handle.cleanup().add_cleanup_on(on, move || {
+                    let mut r = ran.get();
+                    if let Some(r) = r.get_mut(i) {
+                        *r = true;
+                    }
+                    ran.set(r);
+                });
+            }
+        }
+
+        test_harness::run_local(async {
+            let ran = Rc::new(Cell::new([false; 2]));
+
+            let completes = spawn_local_handle(async {});
+            add_all(&completes, &ran);
+            task::yield_now().await;
+            assert_eq!(ran.replace([false; 2]), [true, false]);
+            drop(completes);
+            assert_eq!(ran.get(), [false; 2]);
+
+            let aborted = spawn_local_handle(future::pending());
+            add_all(&aborted, &ran);
+            task::yield_now().await;
+            drop(aborted);
+            assert_eq!(ran.get(), [false, true]);
+        });
+    }
+
+    #[test]
+    fn cleanup_added_from_cleanup() {
+        test_harness::run_local(async {
+            let ran = Rc::new(Cell::new(0_i32));
+            let handle = spawn_local_handle(future::pending());
+            let nested = handle.cleanup();
+            handle.cleanup().add_cleanup_on(CleanupOn::Abort, {
+                let ran = Rc::clone(&ran);
+                let nested = Cell::new(Some(nested));
+                move || {
+                    ran.set(ran.get() + 1_i32);
+                    if let Some(nested) = nested.take() {
+                        let ran = Rc::clone(&ran);
+                        nested.add_cleanup_on(CleanupOn::Abort, move || ran.set(ran.get() + 1_i32));
+                    }
+                }
+            });
+
+            task::yield_now().await;
+            drop(handle);
+            assert_eq!(ran.get(), 2_i32);
+        });
+    }
+
+    #[test]
+    fn abort_on_drop() {
+        test_harness::run_local(async {
+            let ran = Rc::new(Cell::new(0_i32));
+            let handle = spawn_local_handle(future::pending());
+            handle.cleanup().add_cleanup_on(CleanupOn::Abort, {
+                let ran = Rc::clone(&ran);
+                move || ran.set(ran.get() + 1_i32)
+            });
+
+            task::yield_now().await;
+            drop(handle.cleanup().abort_on_drop());
+            assert_eq!(ran.get(), 1_i32);
+            // Already aborted, so the cleanup doesn't run again
+            drop(handle);
+            assert_eq!(ran.get(), 1_i32);
+        });
+    }
+}
@@ -1,8 +1,13 @@
-use std::collections::{hash_map::Values, HashMap};
+use std::{
+    collections::{btree_map::Values, BTreeMap},
+    mem,
+};
 
+/// Map of values to the [`Handle`]s they can be removed with
+/// Ids only increase & are ordered, so iterating goes in insertion order
 pub(crate) struct HandleMap<T> {
     next_id: usize,
-    map: HashMap<usize, T>,
+    map: BTreeMap<usize, T>,
 }
 
 pub(crate) struct Handle {
@@ -10,10 +15,10 @@ pub(crate) struct Handle {
 }
 
 impl<T> HandleMap<T> {
-    pub(crate) fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         Self {
             next_id: 0,
-            map: HashMap::new(),
+            map: BTreeMap::new(),
         }
     }
 
@@ -24,13 +29,25 @@ impl<T> HandleMap<T> {
         Handle { id }
     }
 
-    pub(crate) fn remove(&mut self, handle: Handle) {
-        self.map.remove(&handle.id);
+    pub(crate) fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.map.remove(&handle.id)
+    }
+
+    /// Takes every value, ids of the values taken are never reused
+    pub(crate) fn take(&mut self) -> Self {
+        Self {
+            next_id: self.next_id,
+            map: mem::take(&mut self.map),
+        }
     }
 
     pub(crate) fn len(&self) -> usize {
         self.map.len()
     }
+
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.map.retain(|_, v| f(v));
+    }
 }
 
 impl<'iter, T> IntoIterator for &'iter HandleMap<T> {
@@ -3,9 +3,13 @@
 use std::marker::PhantomData;
 
 use serde::{de::DeserializeOwned, Serialize};
-pub use sycamore_query_hydrate_derive::HydratableQuery;
+pub use sycamore_query_hydrate_derive::{HydratableMutation, HydratableQuery};
 
-use crate::{config::error::Error, query::Query};
+use crate::{
+    config::error::Error,
+    mutation::Mutation,
+    query::{Query, QueryOpts},
+};
 
 /// Trait for letting structs safely create a [`HydratableQueryBuilder`]
 ///
@@ -50,6 +54,62 @@ impl<P, R: Serialize + DeserializeOwned, E: Error> HydratableQueryBuilder<P, R,
     /// Creates a new query from the provided query, with a hydratable key
     #[must_use = "Should use return of this function to use a query with a hydration key"]
     pub fn build<'link>(&self, query: &Query<'link, P, R, E>) -> Query<'link, P, R, E> {
-        Query::new_hydratable(query, self.key.clone())
+        Query::new_hydratable(query, self.key.clone(), query.inner.opts.clone())
+    }
+
+    /// Creates a new query from the provided query, with a hydratable key & `opts` in place of the query's own
+    /// Such as an infinite cache time for queries seeded by the server
+    #[must_use = "Should use return of this function to use a query with a hydration key"]
+    pub fn build_with_opts<'link>(
+        &self,
+        query: &Query<'link, P, R, E>,
+        opts: impl Into<QueryOpts<'link, E>>,
+    ) -> Query<'link, P, R, E> {
+        Query::new_hydratable(query, self.key.clone(), opts.into())
+    }
+}
+
+/// Trait for letting structs safely create a [`HydratableMutationBuilder`]
+///
+/// # Safety
+/// Should not be implemented manually, use ``#[derive(HydratableMutation)]`` on a unit struct to reveal the builder function
+/// As the proc macro includes checks that all hydration keys are different
+pub unsafe trait HydratableMutation {
+    /// Parameter of mutation
+    type Param;
+    /// Successful result type of mutation
+    type Result: Serialize + DeserializeOwned;
+    /// Error result type of mutation
+    type Error;
+
+    /// Constructs a builder that can be used to create a hydratable mutation
+    fn builder() -> HydratableMutationBuilder<Self::Param, Self::Result, Self::Error>;
+}
+
+/// Allows creation of hydratable mutations
+#[derive(Debug)]
+pub struct HydratableMutationBuilder<P, R: Serialize + DeserializeOwned, E> {
+    /// Hydration key - automatically set to struct name when using [`HydratableMutationBuilder`]
+    key: String,
+    _phantom: HydratableQueryPhantom<P, R, E>,
+}
+
+impl<P, R: Serialize + DeserializeOwned, E: Error> HydratableMutationBuilder<P, R, E> {
+    /// Creates a new [`HydratableMutationBuilder`] with a given key
+    ///
+    /// # Safety
+    /// Use ``#[derive(HydratableMutation)]`` instead
+    #[must_use = "No need to construct if you don't call build"]
+    pub const unsafe fn new(key: String) -> Self {
+        Self {
+            key,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new mutation from the provided mutation, with a hydratable key
+    #[must_use = "Should use return of this function to use a mutation with a hydration key"]
+    pub fn build<'link>(&self, mutation: &Mutation<'link, P, R, E>) -> Mutation<'link, P, R, E> {
+        Mutation::new_hydratable(mutation, self.key.clone())
     }
 }
@@ -108,7 +108,12 @@ mod futures;
 mod handle_map;
 mod listenable;
 mod ptr_hash;
+mod random;
 mod sleep;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_harness;
+#[cfg(feature = "tracing")]
+mod trace;
 mod weak_link;
 
 /// Cache Queries and Mutations
@@ -119,6 +124,8 @@ pub mod client;
 pub mod config;
 /// Const default trait
 pub mod const_default;
+/// Fetch metrics
+pub mod metrics;
 /// [`crate::mutation::Mutation`]
 pub mod mutation;
 /// [`crate::query::Query`]
@@ -134,9 +141,21 @@ pub mod hydrate;
 #[cfg(feature = "sycamore")]
 pub mod sycamore;
 
-#[cfg(test)]
+// Only used by tests of the serde feature
+#[cfg(all(test, not(feature = "serde")))]
+use serde_json as _;
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use crate::{client::QueryClient, query::Query, status::FetchResult};
+    use std::time::Duration;
+
+    use crate::{
+        client::QueryClient,
+        config::CacheTime,
+        query::{Query, QueryOpts},
+        status::FetchResult,
+        test_harness,
+    };
 
     fn check<E>(res: FetchResult<i32, E>, exp: i32) {
         match res {
@@ -145,16 +164,40 @@ mod tests {
         }
     }
 
-    #[tokio::test]
-    async fn multiple_client_query() {
-        let client1 = QueryClient::default();
-        let client2 = QueryClient::default();
-        let query1 = Query::new(|| Box::pin(async { Ok::<i32, ()>(12345_i32) }));
-        let query2 = Query::new(|| Box::pin(async { Ok::<i32, ()>(67890_i32) }));
+    #[test]
+    fn multiple_client_query() {
+        test_harness::run_local(async {
+            let client1 = QueryClient::default();
+            let client2 = QueryClient::default();
+            let query1 = Query::new(|| Box::pin(async { Ok::<i32, ()>(12345_i32) }));
+            let query2 = Query::new(|| Box::pin(async { Ok::<i32, ()>(67890_i32) }));
+
+            check(client1.fetch(&query1).await, 12345_i32);
+            check(client2.fetch(&query1).await, 12345_i32);
+            check(client1.fetch(&query2).await, 67890_i32);
+            check(client2.fetch(&query2).await, 67890_i32);
+        });
+    }
+
+    #[test]
+    fn cache_eviction() {
+        test_harness::run_local(async {
+            let client = QueryClient::default();
+            let query = Query::new_with_opts(
+                || Box::pin(async { Ok::<i32, ()>(1_i32) }),
+                QueryOpts::with_cache_time(CacheTime::Duration(Duration::from_secs(5))),
+            );
+
+            let guard = client.subscribe_query(&query, |_| {});
+            check(client.fetch(&query).await, 1_i32);
+            test_harness::advance(Duration::from_secs(10)).await;
+            assert!(client.query_data(&query).is_some());
 
-        check(client1.fetch(&query1).await, 12345_i32);
-        check(client2.fetch(&query1).await, 12345_i32);
-        check(client1.fetch(&query2).await, 67890_i32);
-        check(client2.fetch(&query2).await, 67890_i32);
+            drop(guard);
+            test_harness::advance(Duration::from_secs(4)).await;
+            assert!(client.query_data(&query).is_some());
+            test_harness::advance(Duration::from_secs(2)).await;
+            assert!(client.query_data(&query).is_none());
+        });
     }
 }
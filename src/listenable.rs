@@ -7,6 +7,14 @@ type ListenerFn<'func, T> = Box<dyn Fn(T) + 'func>;
 pub(crate) struct Listener<'func, T> {
     pub(crate) f: ListenerFn<'func, T>,
     pub(crate) drop_f: Option<Box<dyn FnOnce() + 'func>>,
+    /// Checked before each notification, the listener is removed once this returns false
+    pub(crate) alive: Option<Box<dyn Fn() -> bool + 'func>>,
+}
+
+impl<T> Listener<'_, T> {
+    fn is_alive(&self) -> bool {
+        self.alive.as_ref().is_none_or(|alive| alive())
+    }
 }
 
 impl<T> Drop for Listener<'_, T> {
@@ -17,16 +25,21 @@ impl<T> Drop for Listener<'_, T> {
     }
 }
 
+/// A value that notifies its listeners each time it's set or modified
+/// Listeners are always notified in the order they were added
 pub(crate) struct Listenable<'func, T> {
     value: T,
     listeners: HandleMap<Listener<'func, T>>,
+    /// Whether a notification is pending, while notifications are deferred by a batch, see [`Self::batch`]
+    batched: Option<bool>,
 }
 
 impl<'func, T> Listenable<'func, T> {
-    pub(crate) fn new(value: T) -> Self {
+    pub(crate) const fn new(value: T) -> Self {
         Self {
             value,
             listeners: HandleMap::new(),
+            batched: None,
         }
     }
 
@@ -34,6 +47,7 @@ impl<'func, T> Listenable<'func, T> {
         self.listeners.insert(Listener {
             f: Box::new(f),
             drop_f: None,
+            alive: None,
         })
     }
 
@@ -46,10 +60,8 @@ impl<'func, T> Listenable<'func, T> {
         self.listeners.len()
     }
 
-    // Self is drop, can't be consumed by const fn
-    #[allow(clippy::missing_const_for_fn)]
-    pub(crate) fn unwrap(self) -> T {
-        self.value
+    pub(crate) fn listener_count(&self) -> usize {
+        self.listeners.len()
     }
 }
 
@@ -72,7 +84,41 @@ impl<'func, T: Clone> Listenable<'func, T> {
         ret
     }
 
-    fn notify(this: &Self) {
+    /// Defers notifying listeners until `f` returns, then notifies them once with the final value if it was set or modified
+    // Fetches span their batch across polling the query function, so use `start_batch` & `end_batch` instead
+    #[allow(dead_code)]
+    pub(crate) fn batch<R>(this: &mut Self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let started = Self::start_batch(this);
+        let ret = f(this);
+        if started {
+            Self::end_batch(this);
+        }
+        ret
+    }
+
+    /// Defers notifying listeners until [`Self::end_batch`], for batches that can't be contained in a closure, see [`Self::batch`]
+    /// Returns false if a batch had already been started, which this is then part of
+    pub(crate) fn start_batch(this: &mut Self) -> bool {
+        let started = this.batched.is_none();
+        this.batched.get_or_insert(false);
+        started
+    }
+
+    /// Ends the batch, notifying listeners if the value was set or modified during it
+    /// Does nothing if there's no batch
+    pub(crate) fn end_batch(this: &mut Self) {
+        if this.batched.take() == Some(true) {
+            Self::notify(this);
+        }
+    }
+
+    fn notify(this: &mut Self) {
+        if let Some(ref mut pending) = this.batched {
+            *pending = true;
+            return;
+        }
+
+        this.listeners.retain(Listener::is_alive);
         for listener in &this.listeners {
             (listener.f)(this.value.clone());
         }
@@ -92,3 +138,65 @@ impl<T> Deref for Listenable<'_, T> {
         &self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use super::Listenable;
+
+    #[test]
+    fn batch_notifies_once() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut listenable = Listenable::new(0_i32);
+        listenable.add_listener({
+            let seen = Rc::clone(&seen);
+            move |v| seen.borrow_mut().push(v)
+        });
+
+        Listenable::batch(&mut listenable, |l| {
+            Listenable::set(l, 1_i32);
+            Listenable::modify(l, |v| *v += 1_i32);
+        });
+        assert_eq!(*seen.borrow(), [2_i32]);
+
+        Listenable::batch(&mut listenable, |_| {});
+        assert_eq!(*seen.borrow(), [2_i32]);
+    }
+
+    #[test]
+    fn nested_batch() {
+        let count = Rc::new(Cell::new(0_i32));
+        let mut listenable = Listenable::new(0_i32);
+        listenable.add_listener({
+            let count = Rc::clone(&count);
+            move |_| count.set(count.get() + 1_i32)
+        });
+
+        Listenable::batch(&mut listenable, |l| {
+            Listenable::batch(l, |l| Listenable::set(l, 1_i32));
+            assert_eq!(count.get(), 0_i32);
+            Listenable::set(l, 2_i32);
+        });
+        assert_eq!(count.get(), 1_i32);
+    }
+
+    #[test]
+    fn notify_order() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let mut listenable = Listenable::new(0_i32);
+        for i in 0_i32..3_i32 {
+            let order = Rc::clone(&order);
+            listenable.add_listener(move |_| order.borrow_mut().push(i));
+        }
+
+        for value in 1_i32..=3_i32 {
+            order.borrow_mut().clear();
+            Listenable::set(&mut listenable, value);
+            assert_eq!(*order.borrow(), [0_i32, 1_i32, 2_i32]);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Recent fetch timings & results for a query on a client, see [`crate::client::QueryClient::query_metrics`]
+/// Each execution of the query function is recorded, including retries
+#[derive(Debug, Clone)]
+pub struct FetchMetrics {
+    durations: VecDeque<Duration>,
+    successes: u64,
+    errors: u64,
+}
+
+impl Default for FetchMetrics {
+    fn default() -> Self {
+        Self {
+            durations: VecDeque::with_capacity(Self::CAPACITY),
+            successes: 0,
+            errors: 0,
+        }
+    }
+}
+
+impl FetchMetrics {
+    /// Number of recent durations kept, older ones are discarded
+    pub const CAPACITY: usize = 16;
+
+    pub(crate) fn record(&mut self, duration: Duration, success: bool) {
+        if self.durations.len() == Self::CAPACITY {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration);
+
+        if success {
+            self.successes = self.successes.saturating_add(1);
+        } else {
+            self.errors = self.errors.saturating_add(1);
+        }
+    }
+
+    /// Durations of the most recent executions, oldest first
+    #[must_use = "Has no effect other than to get the durations"]
+    #[inline]
+    pub fn durations(&self) -> impl ExactSizeIterator<Item = Duration> + '_ {
+        self.durations.iter().copied()
+    }
+
+    /// Average of [`Self::durations`], `None` if there have been no executions
+    #[must_use = "Has no effect other than to calculate the average"]
+    pub fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.durations.len())
+            .ok()
+            .filter(|c| *c != 0)?;
+        Some(self.durations.iter().sum::<Duration>() / count)
+    }
+
+    /// Total number of successful executions
+    #[must_use = "Has no effect other than to get the count"]
+    #[inline]
+    pub const fn successes(&self) -> u64 {
+        self.successes
+    }
+
+    /// Total number of executions that returned an error
+    #[must_use = "Has no effect other than to get the count"]
+    #[inline]
+    pub const fn errors(&self) -> u64 {
+        self.errors
+    }
+}
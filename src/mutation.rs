@@ -9,11 +9,12 @@ use std::{
 
 use crate::{
     cache::{CacheControl, Cacheable},
-    config::{retry::RetryConfig, CacheTime, NetworkMode, SetOption},
+    config::{error::Error, retry::RetryConfig, CacheTime, NetworkMode, SetOption},
     const_default::ConstDefault,
     listenable::Listenable,
     query::QueryOpts,
-    status::MutationData,
+    sleep,
+    status::{MutateError, MutationData},
     weak_link::WeakLink,
 };
 
@@ -38,6 +39,24 @@ impl<E: ?Sized> Clone for MutationOpts<'_, E> {
     }
 }
 
+impl<E: ?Sized> From<CacheTime> for MutationOpts<'_, E> {
+    fn from(value: CacheTime) -> Self {
+        Self::with_cache_time(value)
+    }
+}
+
+impl<E: ?Sized> From<NetworkMode> for MutationOpts<'_, E> {
+    fn from(value: NetworkMode) -> Self {
+        Self::with_network_mode(value)
+    }
+}
+
+impl<'cfg, E: ?Sized> From<RetryConfig<'cfg, E>> for MutationOpts<'cfg, E> {
+    fn from(value: RetryConfig<'cfg, E>) -> Self {
+        Self::with_retry(value)
+    }
+}
+
 impl<E: ?Sized> ConstDefault for MutationOpts<'_, E> {
     const DEFAULT: Self = Self::const_default();
 }
@@ -65,6 +84,27 @@ impl<'cfg, E: ?Sized> MutationOpts<'cfg, E> {
         }
     }
 
+    /// New options that inherrit all except [`MutationOpts.cache_time`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_cache_time(cache_time: CacheTime) -> Self {
+        Self::new().set_cache_time(cache_time)
+    }
+
+    /// New options that inherrit all except [`MutationOpts.network_mode`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_network_mode(network_mode: NetworkMode) -> Self {
+        Self::new().set_network_mode(network_mode)
+    }
+
+    /// New options that inherrit all except [`MutationOpts.retry`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub fn with_retry(retry: RetryConfig<'cfg, E>) -> Self {
+        Self::new().set_retry(retry)
+    }
+
     /// Sets [`MutationOpts.cache_time`]
     #[must_use = "Builder pattern"]
     #[inline]
@@ -90,6 +130,17 @@ impl<'cfg, E: ?Sized> MutationOpts<'cfg, E> {
         self.retry = SetOption::set(retry);
         self
     }
+
+    /// Layers `other` over these options, anything set in `other` is used in place of the value here
+    #[must_use = "Has no effect other than to return the merged options"]
+    #[inline]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cache_time: self.cache_time.merge(other.cache_time),
+            network_mode: self.network_mode.merge(other.network_mode),
+            retry: self.retry.merge(other.retry),
+        }
+    }
 }
 
 impl<'cfg, E: ?Sized> From<QueryOpts<'cfg, E>> for MutationOpts<'cfg, E> {
@@ -108,6 +159,7 @@ type OnSuccess<P, R, C> = dyn for<'cb> Fn(Rc<R>, &'cb P, &'cb Option<C>) -> Call
 type OnError<P, E, C> = dyn for<'cb> Fn(Rc<E>, &'cb P, &'cb Option<C>) -> CallbackFuture<'cb, ()>;
 type OnSettled<P, R, E, C> =
     dyn for<'cb> Fn(Result<Rc<R>, Rc<E>>, &'cb P, &'cb Option<C>) -> CallbackFuture<'cb, ()>;
+type OnNoConnection<P, C> = dyn for<'cb> Fn(&'cb P, &'cb Option<C>) -> CallbackFuture<'cb, ()>;
 type OnMutate<P, C> = dyn for<'cb> Fn(&'cb mut P) -> CallbackFuture<'cb, Option<C>>;
 
 /// Callbacks for when a mutation is initiated or has finished
@@ -115,6 +167,7 @@ pub struct MutationCallbacks<P, R, E, C> {
     pub(crate) on_success: Option<Box<OnSuccess<P, R, C>>>,
     pub(crate) on_error: Option<Box<OnError<P, E, C>>>,
     pub(crate) on_settled: Option<Box<OnSettled<P, R, E, C>>>,
+    pub(crate) on_no_connection: Option<Box<OnNoConnection<P, C>>>,
     pub(crate) on_mutate: Option<Box<OnMutate<P, C>>>,
 }
 
@@ -124,6 +177,7 @@ impl<P, R, E, C> Debug for MutationCallbacks<P, R, E, C> {
             .field("on_success", &"..")
             .field("on_error", &"..")
             .field("on_settled", &"..")
+            .field("on_no_connection", &"..")
             .field("on_mutate", &"..")
             .finish()
     }
@@ -139,6 +193,7 @@ impl<P, R, E, C> MutationCallbacks<P, R, E, C> {
             on_success: None,
             on_error: None,
             on_settled: None,
+            on_no_connection: None,
             on_mutate: None,
         }
     }
@@ -168,6 +223,7 @@ impl<P, R, E, C> MutationCallbacks<P, R, E, C> {
     }
 
     /// Add settled callback that will be called if the mutation succeeds or not
+    /// It isn't called if the mutation couldn't run, see [`Self::on_no_connection`]
     #[must_use = "Used to construct callbacks for a mutation"]
     #[inline]
     pub fn on_settled<F>(mut self, on_settled: F) -> Self
@@ -179,6 +235,18 @@ impl<P, R, E, C> MutationCallbacks<P, R, E, C> {
         self
     }
 
+    /// Add no connection callback
+    /// Will execute instead of the other callbacks if the mutation couldn't run without an internet connection, such as to roll back changes made by `on_mutate`
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn on_no_connection<F>(mut self, on_no_connection: F) -> Self
+    where
+        for<'cb> F: Fn(&'cb P, &'cb Option<C>) -> CallbackFuture<'cb, ()> + 'cb,
+    {
+        self.on_no_connection = Some(Box::new(on_no_connection));
+        self
+    }
+
     /// Add mutate callback that will be called when the mutation begins
     /// Must return a context object that will be passed to the other callbacks: `C`
     #[must_use = "Used to construct callbacks for a mutation"]
@@ -199,9 +267,125 @@ impl<P, R, E, C> Default for MutationCallbacks<P, R, E, C> {
     }
 }
 
-pub(crate) struct MutateMeta<'link, /*P*/ R, E /*C*/> {
-    pub(crate) data: Listenable<'link, MutationData<R, E>>,
+// Wrapping closures are generic over these, so they must be 'static for the wrapper to be
+impl<P: 'static, R: 'static, E: 'static, C: 'static> MutationCallbacks<P, R, E, C> {
+    /// Container for callbacks for a mutation, where `on_mutate` always returns a context object `C`
+    /// The other callbacks are given `&C` directly, rather than `&Option<C>`
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn with_context<F>(on_mutate: F) -> ContextCallbacks<P, R, E, C>
+    where
+        for<'cb> F: Fn(&'cb mut P) -> CallbackFuture<'cb, C> + 'cb,
+    {
+        ContextCallbacks {
+            inner: Self::new().on_mutate(move |value| {
+                let context = on_mutate(value);
+                Box::pin(async move { Some(context.await) })
+            }),
+        }
+    }
+}
+
+/// Callbacks for a mutation with a context object that is always set, see [`MutationCallbacks::with_context`]
+/// Converted into [`MutationCallbacks`] to be used
+///
+/// If the default callbacks given to [`crate::client::QueryClient::mutate`] have their own `on_mutate`, it is used instead, and these callbacks are skipped if it doesn't return a context
+pub struct ContextCallbacks<P, R, E, C> {
+    inner: MutationCallbacks<P, R, E, C>,
+}
+
+impl<P, R, E, C> Debug for ContextCallbacks<P, R, E, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextCallbacks")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// Wrapping closures are generic over these, so they must be 'static for the wrapper to be
+impl<P: 'static, R: 'static, E: 'static, C: 'static> ContextCallbacks<P, R, E, C> {
+    /// Add success callback
+    /// Will execute when the callback has finished successfully
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn on_success<F>(mut self, on_success: F) -> Self
+    where
+        for<'cb> F: Fn(Rc<R>, &'cb P, &'cb C) -> CallbackFuture<'cb, ()> + 'cb,
+    {
+        self.inner = self.inner.on_success(move |data, value, context| {
+            context.as_ref().map_or_else(
+                || -> CallbackFuture<'_, ()> { Box::pin(async {}) },
+                |context| on_success(data, value, context),
+            )
+        });
+        self
+    }
+
+    /// Add error callback
+    /// Will execute when the callback has finished with an error
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn on_error<F>(mut self, on_error: F) -> Self
+    where
+        for<'cb> F: Fn(Rc<E>, &'cb P, &'cb C) -> CallbackFuture<'cb, ()> + 'cb,
+    {
+        self.inner = self.inner.on_error(move |error, value, context| {
+            context.as_ref().map_or_else(
+                || -> CallbackFuture<'_, ()> { Box::pin(async {}) },
+                |context| on_error(error, value, context),
+            )
+        });
+        self
+    }
+
+    /// Add settled callback that will be called if the mutation succeeds or not
+    /// It isn't called if the mutation couldn't run, see [`Self::on_no_connection`]
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn on_settled<F>(mut self, on_settled: F) -> Self
+    where
+        for<'cb> F: Fn(Result<Rc<R>, Rc<E>>, &'cb P, &'cb C) -> CallbackFuture<'cb, ()> + 'cb,
+    {
+        self.inner = self.inner.on_settled(move |result, value, context| {
+            context.as_ref().map_or_else(
+                || -> CallbackFuture<'_, ()> { Box::pin(async {}) },
+                |context| on_settled(result, value, context),
+            )
+        });
+        self
+    }
+
+    /// Add no connection callback
+    /// Will execute instead of the other callbacks if the mutation couldn't run without an internet connection
+    #[must_use = "Used to construct callbacks for a mutation"]
+    #[inline]
+    pub fn on_no_connection<F>(mut self, on_no_connection: F) -> Self
+    where
+        for<'cb> F: Fn(&'cb P, &'cb C) -> CallbackFuture<'cb, ()> + 'cb,
+    {
+        self.inner = self.inner.on_no_connection(move |value, context| {
+            context.as_ref().map_or_else(
+                || -> CallbackFuture<'_, ()> { Box::pin(async {}) },
+                |context| on_no_connection(value, context),
+            )
+        });
+        self
+    }
+}
+
+impl<P, R, E, C> From<ContextCallbacks<P, R, E, C>> for MutationCallbacks<P, R, E, C> {
+    #[inline]
+    fn from(value: ContextCallbacks<P, R, E, C>) -> Self {
+        value.inner
+    }
+}
+
+pub(crate) struct MutateMeta<'link, P, R, E /*C*/> {
+    pub(crate) data: Listenable<'link, MutationData<P, R, E>>,
+    /// Id of the latest mutation to be initiated
     pub(crate) id: usize,
+    /// Id of this entry, unlike `id` it doesn't change for each mutation
+    pub(crate) entry_id: usize,
     pub(crate) cache_control: CacheControl<'link>,
 }
 
@@ -213,16 +397,16 @@ pub struct Mutation<'link, P, R, E /*C*/> {
     pub(crate) inner: Rc<MutationInner<'link, P, R, E /*C*/>>,
 }
 
-pub(crate) struct MutationInner<'link, P: 'link, R, E /*C*/> {
+pub(crate) struct MutationInner<'link, P, R, E /*C*/> {
     pub(crate) opts: MutationOpts<'link, E>,
     func: Rc<MutationFn<P, R, E>>,
-    pub(crate) link: WeakLink<'link, MutateMeta<'link, /*P*/ R, E /*C*/>>,
+    pub(crate) link: WeakLink<'link, MutateMeta<'link, P, R, E /*C*/>>,
     // TODO
     hydration_key: Option<String>,
 }
 
 impl<'link, P, R, E> Cacheable<'link> for Weak<MutationInner<'link, P, R, E>> {
-    type LinkData = MutateMeta<'link, R, E>;
+    type LinkData = MutateMeta<'link, P, R, E>;
 
     #[inline]
     fn link(&self) -> Option<WeakLink<'link, Self::LinkData>> {
@@ -240,6 +424,14 @@ impl<P, R, E> Debug for Mutation<'_, P, R, E> {
 }
 
 impl<'link, P, R, E> Mutation<'link, P, R, E> {
+    /// Identity of this mutation, stable for as long as it's alive
+    /// Can be used to key maps of mutations outside of a [`crate::client::QueryClient`]
+    #[must_use = "Has no effect other than to return the id"]
+    #[inline]
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.inner).cast::<()>() as usize
+    }
+
     /// Create a new mutation
     #[must_use = "Creating a mutation as no effect"]
     #[inline]
@@ -267,6 +459,18 @@ impl<'link, P, R, E> Mutation<'link, P, R, E> {
         }
     }
 
+    #[inline]
+    pub(crate) fn new_hydratable(mutation: &Self, hydration_key: String) -> Self {
+        Self {
+            inner: Rc::new(MutationInner {
+                opts: mutation.inner.opts.clone(),
+                func: Rc::clone(&mutation.inner.func),
+                link: WeakLink::new(),
+                hydration_key: Some(hydration_key),
+            }),
+        }
+    }
+
     /// Directly execute mutation without a client
     ///
     /// # Errors
@@ -275,6 +479,55 @@ impl<'link, P, R, E> Mutation<'link, P, R, E> {
     pub async fn execute<'cb>(&self, value: &'cb P) -> Result<R, E> {
         self.inner.execute(value).await
     }
+
+    /// Directly execute mutation without a client, applying its [`RetryConfig`] & [`NetworkMode`]
+    /// Options set in `opts` override the mutation's own for this execution only, anything unset in both uses the default
+    ///
+    /// # Errors
+    /// Will error if the provided mutation function does once out of retries, or if there is no internet connection
+    pub async fn execute_with_opts<'cb>(
+        &self,
+        value: &'cb P,
+        opts: impl Into<MutationOpts<'link, E>>,
+    ) -> Result<R, MutateError<E>>
+    where
+        E: Error,
+    {
+        let opts = opts.into();
+        let retry = match opts.retry {
+            SetOption::Set(retry) => retry,
+            SetOption::Inherrit => match self.inner.opts.retry {
+                SetOption::Set(ref retry) => retry.clone(),
+                SetOption::Inherrit => RetryConfig::default(),
+            },
+        };
+        #[cfg(target_arch = "wasm32")]
+        let network_mode = match (opts.network_mode, self.inner.opts.network_mode) {
+            (SetOption::Set(n), _) | (SetOption::Inherrit, SetOption::Set(n)) => n,
+            (SetOption::Inherrit, SetOption::Inherrit) => NetworkMode::default(),
+        };
+
+        let started = sleep::Instant::now();
+        let mut count = 1;
+        loop {
+            #[cfg(target_arch = "wasm32")]
+            if !network_mode.should_try(count) && !crate::browser::online_handler::is_online() {
+                return Err(MutateError::NoConnection);
+            }
+
+            let e = match self.inner.execute(value).await {
+                Ok(r) => return Ok(r),
+                Err(e) => Rc::new(e),
+            };
+            let Some(delay) = retry.retry_delay(count, Rc::clone(&e), started.elapsed()) else {
+                return Err(MutateError::FnError(e));
+            };
+
+            retry.notify_retry(count, e);
+            sleep::sleep(delay).await;
+            count = count.saturating_add(1);
+        }
+    }
 }
 
 impl<P, R, E> MutationInner<'_, P, R, E> {
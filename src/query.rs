@@ -1,27 +1,183 @@
 use std::{
+    any::Any,
+    cell::RefCell,
+    collections::HashMap,
     fmt::{self, Debug, Formatter},
-    future::Future,
+    future::{self, Future},
+    hash::Hash,
     pin::Pin,
+    ptr,
     rc::{Rc, Weak},
 };
 
+use tokio::sync::Notify;
+
 use crate::{
     cache::{CacheControl, Cacheable},
+    client::QueryClient,
     config::{error::Error, retry::RetryConfig, CacheTime, NetworkMode, SetOption},
     const_default::ConstDefault,
-    futures::future_handle::FutureHandle,
+    futures::future_handle::AbortOnDrop,
     handle_map::HandleMap,
     listenable::Listenable,
+    metrics::FetchMetrics,
     mutation::MutationOpts,
-    status::QueryData,
+    status::{CancelReason, FetchResult, Instant, PendingStatus, QueryData, QueryStatus},
     weak_link::WeakLink,
 };
 
+pub(crate) type FetchFuture<'func, R, E> = Pin<Box<dyn Future<Output = FetchResult<R, E>> + 'func>>;
+pub(crate) type RefetchFn<'func, R, E> =
+    Rc<dyn Fn(&QueryClient<'func>) -> FetchFuture<'func, R, E> + 'func>;
+
 pub(crate) struct FetchMeta<'link, R, E> {
     pub(crate) data: Listenable<'link, QueryData<R, E>>,
+    /// Id of the latest fetch generation, changed to supersede any fetch in progress
     pub(crate) id: usize,
-    pub(crate) future_handles: HandleMap<FutureHandle<'link>>,
+    /// Id of this entry, unlike `id` it doesn't change when fetches are superseded
+    pub(crate) entry_id: usize,
     pub(crate) cache_control: CacheControl<'link>,
+    pub(crate) metrics: FetchMetrics,
+    /// Refetches with the last arg it was fetched with or given to [`QueryClient::subscribe_query_with_arg`], if any
+    pub(crate) refetch: Option<RefetchFn<'link, R, E>>,
+    /// Number of [`QueryClient::fetch_and_retain`] guards keeping this entry active without a listener
+    pub(crate) retained: usize,
+    /// When a fetch last succeeded
+    pub(crate) data_updated_at: Option<Instant>,
+    /// When a fetch last failed, once out of retries
+    pub(crate) error_updated_at: Option<Instant>,
+    /// Fetch in progress that others can join, kept alive by the fetch itself
+    pub(crate) shared_fetch: Weak<SharedFetch<R, E>>,
+    /// Futures spawned for this entry, aborted if it's removed before they complete
+    pub(crate) future_handles: HandleMap<AbortOnDrop<'link>>,
+}
+
+impl<R, E> FetchMeta<'_, R, E> {
+    /// Sets the data & notifies listeners, see [`Listenable::set`]
+    pub(crate) fn set_data(&mut self, data: QueryData<R, E>) -> QueryData<R, E> {
+        let ret = Listenable::set(&mut self.data, data);
+        self.sync_active();
+        ret
+    }
+
+    /// Modifies the data & notifies listeners, see [`Listenable::modify`]
+    pub(crate) fn modify_data<T>(&mut self, func: impl Fn(&mut QueryData<R, E>) -> T) -> T {
+        let ret = Listenable::modify(&mut self.data, func);
+        self.sync_active();
+        ret
+    }
+
+    /// Defers notifying listeners of the data until [`Self::end_batch`], see [`Listenable::start_batch`]
+    pub(crate) fn start_batch(&mut self) {
+        Listenable::start_batch(&mut self.data);
+    }
+
+    /// Notifies listeners once for everything set since [`Self::start_batch`], see [`Listenable::end_batch`]
+    pub(crate) fn end_batch(&mut self) {
+        Listenable::end_batch(&mut self.data);
+        self.sync_active();
+    }
+
+    /// Marks the data as no longer loading, once its fetch has been cancelled
+    pub(crate) fn settle_cancelled(&mut self) {
+        if self.data.is_fetching() {
+            self.modify_data(|d| match *d {
+                QueryData::Ok(_, ref mut s) | QueryData::Err(_, ref mut s) => {
+                    *s = QueryStatus::Idle;
+                }
+                QueryData::Pending(ref mut s) => *s = PendingStatus::Idle,
+            });
+        }
+    }
+
+    /// Notifying may prune dead listeners, the entry is no longer active if none are left & it isn't retained
+    pub(crate) fn sync_active(&self) {
+        if self.data.listener_count() == 0 && self.retained == 0 {
+            self.cache_control.set_active(false);
+        }
+    }
+}
+
+/// A fetch in progress, stored on its entry so every client attached to the cache can join it rather than run the query again
+pub(crate) struct SharedFetch<R, E> {
+    /// Id of the entry when the fetch started, only joined while it's still current
+    pub(crate) id: usize,
+    result: RefCell<Option<Joined<R, E>>>,
+    done: Notify,
+}
+
+/// Result of a [`SharedFetch`] given to those that joined it
+pub(crate) enum Joined<R, E> {
+    Fresh(Result<Rc<R>, Rc<E>>),
+    Cancelled(CancelReason),
+    /// The fetch didn't finish with a result that can be shared, so whatever joined it should fetch itself
+    Rerun,
+}
+
+impl<R, E> Clone for Joined<R, E> {
+    fn clone(&self) -> Self {
+        match *self {
+            Self::Fresh(Ok(ref r)) => Self::Fresh(Ok(Rc::clone(r))),
+            Self::Fresh(Err(ref e)) => Self::Fresh(Err(Rc::clone(e))),
+            Self::Cancelled(reason) => Self::Cancelled(reason),
+            Self::Rerun => Self::Rerun,
+        }
+    }
+}
+
+impl<R, E> SharedFetch<R, E> {
+    pub(crate) fn new(id: usize) -> Self {
+        Self {
+            id,
+            result: RefCell::new(None),
+            done: Notify::new(),
+        }
+    }
+
+    /// Whether the fetch is still in progress, so can be joined
+    pub(crate) fn pending(&self) -> bool {
+        self.result.borrow().is_none()
+    }
+
+    /// Shares the outcome of the fetch with everything that joined it
+    pub(crate) fn finish(&self, result: &FetchResult<R, E>) {
+        let joined = match *result {
+            FetchResult::Fresh(Ok(ref r)) => Joined::Fresh(Ok(Rc::clone(r))),
+            FetchResult::Fresh(Err(ref e)) => Joined::Fresh(Err(Rc::clone(e))),
+            FetchResult::Cancelled(reason) => Joined::Cancelled(reason),
+            FetchResult::Stale(_) | FetchResult::NoConnection(_) => Joined::Rerun,
+        };
+        self.set(joined);
+    }
+
+    /// Sets the outcome unless it's already finished, waking everything that joined
+    fn set(&self, joined: Joined<R, E>) {
+        if self.pending() {
+            *self.result.borrow_mut() = Some(joined);
+            self.done.notify_waiters();
+        }
+    }
+
+    /// Waits for the outcome of the fetch
+    pub(crate) async fn join(&self) -> Joined<R, E> {
+        loop {
+            // Created before checking, so finishing in between can't be missed
+            let done = self.done.notified();
+            if let Some(ref joined) = *self.result.borrow() {
+                return joined.clone();
+            }
+            done.await;
+        }
+    }
+}
+
+/// Held by the fetch leading a [`SharedFetch`], so anything that joined it fetches itself if it's dropped before finishing
+pub(crate) struct LeadFetch<R, E>(pub(crate) Rc<SharedFetch<R, E>>);
+
+impl<R, E> Drop for LeadFetch<R, E> {
+    fn drop(&mut self) {
+        self.0.set(Joined::Rerun);
+    }
 }
 
 /// A query funnction that can be executed with or without a client
@@ -32,8 +188,9 @@ pub struct Query<'link, P, R, E> {
 impl<P, R, E> Debug for Query<'_, P, R, E> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("Query")
-            .field("func", &"..")
+            .field("func", &self.inner.func)
             .field("hydrate_key", &self.inner.hydrate_key)
+            .field("persist_key", &self.inner.persist.as_ref().map(|p| &p.key))
             .finish_non_exhaustive()
     }
 }
@@ -47,6 +204,13 @@ pub struct QueryOpts<'cfg, E: ?Sized> {
     pub network_mode: SetOption<NetworkMode>,
     /// See [`RetryConfig`]
     pub retry: SetOption<RetryConfig<'cfg, E>>,
+    /// Whether the final error of a fetch is cached, otherwise the data from before the fetch is kept & the error is only returned to whatever awaited it
+    /// Without data from before the fetch, the entry is left as [`QueryData::Pending`] with [`PendingStatus::Idle`]
+    /// Default: `true`
+    pub cache_errors: SetOption<bool>,
+    /// Whether fetches skip the cache entirely, running the query & returning the result without storing it, such as for a health check
+    /// Default: `false`
+    pub no_cache: SetOption<bool>,
 }
 
 impl<'cfg, E: ?Sized> From<MutationOpts<'cfg, E>> for QueryOpts<'cfg, E> {
@@ -55,6 +219,8 @@ impl<'cfg, E: ?Sized> From<MutationOpts<'cfg, E>> for QueryOpts<'cfg, E> {
             cache_time: value.cache_time,
             network_mode: value.network_mode,
             retry: value.retry,
+            cache_errors: SetOption::Inherrit,
+            no_cache: SetOption::Inherrit,
         }
     }
 }
@@ -65,6 +231,8 @@ impl<E: ?Sized> Clone for QueryOpts<'_, E> {
             cache_time: self.cache_time,
             network_mode: self.network_mode,
             retry: self.retry.clone(),
+            cache_errors: self.cache_errors,
+            no_cache: self.no_cache,
         }
     }
 }
@@ -75,6 +243,24 @@ impl<E: ?Sized> Default for QueryOpts<'_, E> {
     }
 }
 
+impl<E: ?Sized> From<CacheTime> for QueryOpts<'_, E> {
+    fn from(value: CacheTime) -> Self {
+        Self::with_cache_time(value)
+    }
+}
+
+impl<E: ?Sized> From<NetworkMode> for QueryOpts<'_, E> {
+    fn from(value: NetworkMode) -> Self {
+        Self::with_network_mode(value)
+    }
+}
+
+impl<'cfg, E: ?Sized> From<RetryConfig<'cfg, E>> for QueryOpts<'cfg, E> {
+    fn from(value: RetryConfig<'cfg, E>) -> Self {
+        Self::with_retry(value)
+    }
+}
+
 impl<E: ?Sized> ConstDefault for QueryOpts<'_, E> {
     const DEFAULT: Self = Self::const_default();
 }
@@ -88,6 +274,8 @@ impl<'cfg, E: ?Sized> QueryOpts<'cfg, E> {
             cache_time: SetOption::Inherrit,
             network_mode: SetOption::Inherrit,
             retry: SetOption::Inherrit,
+            cache_errors: SetOption::Inherrit,
+            no_cache: SetOption::Inherrit,
         }
     }
 
@@ -99,9 +287,32 @@ impl<'cfg, E: ?Sized> QueryOpts<'cfg, E> {
             cache_time: SetOption::DEFAULT,
             network_mode: SetOption::DEFAULT,
             retry: SetOption::DEFAULT,
+            cache_errors: SetOption::Set(true),
+            no_cache: SetOption::Set(false),
         }
     }
 
+    /// New options that inherrit all except [`QueryOpts.cache_time`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_cache_time(cache_time: CacheTime) -> Self {
+        Self::new().set_cache_time(cache_time)
+    }
+
+    /// New options that inherrit all except [`QueryOpts.network_mode`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub const fn with_network_mode(network_mode: NetworkMode) -> Self {
+        Self::new().set_network_mode(network_mode)
+    }
+
+    /// New options that inherrit all except [`QueryOpts.retry`]
+    #[must_use = "Creating new options has no effect"]
+    #[inline]
+    pub fn with_retry(retry: RetryConfig<'cfg, E>) -> Self {
+        Self::new().set_retry(retry)
+    }
+
     /// Sets [`QueryOpts.cache_time`]
     #[must_use = "Builder pattern"]
     #[inline]
@@ -127,6 +338,35 @@ impl<'cfg, E: ?Sized> QueryOpts<'cfg, E> {
         self.retry = SetOption::set(retry);
         self
     }
+
+    /// Sets [`QueryOpts.cache_errors`]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub const fn set_cache_errors(mut self, cache_errors: bool) -> Self {
+        self.cache_errors = SetOption::set(cache_errors);
+        self
+    }
+
+    /// Sets [`QueryOpts.no_cache`]
+    #[must_use = "Builder pattern"]
+    #[inline]
+    pub const fn set_no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = SetOption::set(no_cache);
+        self
+    }
+
+    /// Layers `other` over these options, anything set in `other` is used in place of the value here
+    #[must_use = "Has no effect other than to return the merged options"]
+    #[inline]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cache_time: self.cache_time.merge(other.cache_time),
+            network_mode: self.network_mode.merge(other.network_mode),
+            retry: self.retry.merge(other.retry),
+            cache_errors: self.cache_errors.merge(other.cache_errors),
+            no_cache: self.no_cache.merge(other.no_cache),
+        }
+    }
 }
 
 pub(crate) struct QueryInner<'link, P: 'link, R, E> {
@@ -135,6 +375,20 @@ pub(crate) struct QueryInner<'link, P: 'link, R, E> {
     pub(crate) link: WeakLink<'link, FetchMeta<'link, R, E>>,
     // TODO
     hydrate_key: Option<String>,
+    pub(crate) persist: Option<Persist<'link, R>>,
+    /// Refetch for queries made without a param, so they can be refetched once subscribed to, even before they've been fetched
+    pub(crate) refetch_no_param: Option<NoParamRefetch<'link, P, R, E>>,
+}
+
+pub(crate) type NoParamRefetch<'link, P, R, E> =
+    fn(&Query<'link, P, R, E>) -> RefetchFn<'link, R, E>;
+
+pub(crate) type PersistFn<'func, R> = Box<dyn Fn(&R) -> Option<String> + 'func>;
+
+/// Serializer registered with [`Query::persistable`], see [`QueryClient::persist_all`]
+pub(crate) struct Persist<'func, R> {
+    pub(crate) key: String,
+    pub(crate) serialize: PersistFn<'func, R>,
 }
 
 impl<'link, P, R, E> Cacheable<'link> for Weak<QueryInner<'link, P, R, E>> {
@@ -150,13 +404,20 @@ pub(crate) type QueryReturn<T, E> = Pin<Box<dyn Future<Output = Result<T, E>>>>;
 pub(crate) type NoParam<'func, R, E> = Box<dyn Fn() -> QueryReturn<R, E> + 'func>;
 pub(crate) type WithParam<'func, P, R, E> = Box<dyn Fn(&P) -> QueryReturn<R, E> + 'func>;
 
-// TODO
-//#[derive(Debug)]
 enum QueryFn<'func, P, R, E> {
     NoParam(NoParam<'func, R, E>),
     WithParam(WithParam<'func, P, R, E>),
 }
 
+impl<P, R, E> Debug for QueryFn<'_, P, R, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NoParam(_) => f.debug_tuple("QueryFn::NoParam").field(&"..").finish(),
+            Self::WithParam(_) => f.debug_tuple("QueryFn::WithParam").field(&"..").finish(),
+        }
+    }
+}
+
 impl<P, R, E> Clone for Query<'_, P, R, E> {
     #[inline]
     fn clone(&self) -> Self {
@@ -166,12 +427,26 @@ impl<P, R, E> Clone for Query<'_, P, R, E> {
     }
 }
 
+impl<P, R, E> Query<'_, P, R, E> {
+    /// Identity of this query, shared by its clones & stable for as long as any of them are alive
+    /// Can be used to key maps of queries outside of a [`crate::client::QueryClient`]
+    #[must_use = "Has no effect other than to return the id"]
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.inner.id()
+    }
+}
+
 impl<'link, R, E: Error> Query<'link, (), R, E> {
     /// Create new [`Query`] with no arguments
     #[must_use = "No reason to create a Query if you don't use it"]
     #[inline]
     pub fn new(func: impl Fn() -> QueryReturn<R, E> + 'link) -> Self {
-        Self::new_inner(QueryFn::NoParam(Box::new(func)), QueryOpts::new())
+        Self::new_inner(
+            QueryFn::NoParam(Box::new(func)),
+            QueryOpts::new(),
+            Some(Self::refetch_no_param),
+        )
     }
 
     /// Create new [`Query`] with no arguments, with configuration options
@@ -181,7 +456,23 @@ impl<'link, R, E: Error> Query<'link, (), R, E> {
         func: impl Fn() -> QueryReturn<R, E> + 'link,
         opts: impl Into<QueryOpts<'link, E>>,
     ) -> Self {
-        Self::new_inner(QueryFn::NoParam(Box::new(func)), opts.into())
+        Self::new_inner(
+            QueryFn::NoParam(Box::new(func)),
+            opts.into(),
+            Some(Self::refetch_no_param),
+        )
+    }
+
+    /// Create new [`Query`] with no arguments from a synchronous function
+    /// The result is wrapped in a ready future, caching & retries behave the same as for [`Self::new`]
+    #[must_use = "No reason to create a Query if you don't use it"]
+    #[inline]
+    pub fn new_sync(func: impl Fn() -> Result<R, E> + 'link) -> Self
+    where
+        R: 'static,
+        E: 'static,
+    {
+        Self::new(move || Box::pin(future::ready(func())))
     }
 
     /// Directly execute query without a client
@@ -192,38 +483,138 @@ impl<'link, R, E: Error> Query<'link, (), R, E> {
     pub async fn execute(&self) -> Result<R, E> {
         self.inner.execute_with_arg(&()).await
     }
+
+    /// See [`QueryInner::refetch_no_param`]
+    fn refetch_no_param(query: &Self) -> RefetchFn<'link, R, E> {
+        QueryClient::refetch_fn(query, Rc::new(()))
+    }
 }
 
 impl<'link, P, R, E: Error> Query<'link, P, R, E> {
     #[inline]
-    fn new_inner(func: QueryFn<'link, P, R, E>, opts: QueryOpts<'link, E>) -> Self {
+    fn new_inner(
+        func: QueryFn<'link, P, R, E>,
+        opts: QueryOpts<'link, E>,
+        refetch_no_param: Option<NoParamRefetch<'link, P, R, E>>,
+    ) -> Self {
         Self {
             inner: Rc::new(QueryInner {
                 opts,
                 func: Rc::new(func),
                 link: WeakLink::new(),
                 hydrate_key: None,
+                persist: None,
+                refetch_no_param,
             }),
         }
     }
 
+    /// New [`Query`] sharing the function of `query`, with a hydration key & `opts`
     #[inline]
-    pub(crate) fn new_hydratable(query: &Self, hydratable_key: String) -> Self {
+    pub(crate) fn new_hydratable(
+        query: &Self,
+        hydratable_key: String,
+        opts: QueryOpts<'link, E>,
+    ) -> Self {
         Self {
             inner: Rc::new(QueryInner {
-                opts: query.inner.opts.clone(),
+                opts,
                 func: Rc::clone(&query.inner.func),
                 link: WeakLink::new(),
                 hydrate_key: Some(hydratable_key),
+                persist: None,
+                refetch_no_param: query.inner.refetch_no_param,
+            }),
+        }
+    }
+
+    /// New [`Query`] sharing the function & options of `query`, but with its own cached data
+    #[inline]
+    fn new_keyed(query: &Self) -> Self {
+        query.with_opts(query.inner.opts.clone())
+    }
+
+    /// Create a new [`Query`] sharing the function of this one, with different configuration options
+    /// The new query has its own cached data, separate from this one
+    #[must_use = "No reason to create a Query if you don't use it"]
+    #[inline]
+    pub fn with_opts(&self, opts: impl Into<QueryOpts<'link, E>>) -> Self {
+        Self {
+            inner: Rc::new(QueryInner {
+                opts: opts.into(),
+                func: Rc::clone(&self.inner.func),
+                link: WeakLink::new(),
+                hydrate_key: None,
+                persist: None,
+                refetch_no_param: self.inner.refetch_no_param,
+            }),
+        }
+    }
+
+    /// Create a new [`Query`] sharing the function & options of this one, whose data is included in [`QueryClient::persist_all`] under `key`
+    /// `serialize` is given the data whenever it's persisted, returning `None` leaves it out
+    /// The new query has its own cached data, separate from this one
+    #[must_use = "No reason to create a Query if you don't use it"]
+    #[inline]
+    pub fn persistable(
+        &self,
+        key: impl Into<String>,
+        serialize: impl Fn(&R) -> Option<String> + 'link,
+    ) -> Self {
+        Self {
+            inner: Rc::new(QueryInner {
+                opts: self.inner.opts.clone(),
+                func: Rc::clone(&self.inner.func),
+                link: WeakLink::new(),
+                hydrate_key: None,
+                persist: Some(Persist {
+                    key: key.into(),
+                    serialize: Box::new(serialize),
+                }),
+                refetch_no_param: self.inner.refetch_no_param,
             }),
         }
     }
 
+    /// Create a new [`Query`] wrapping the function of this one, converting its errors with `f`
+    /// The new query has its own cached data
+    /// [`QueryOpts::retry`] is specific to the error type, so it's inherrited rather than carried over
+    #[must_use = "No reason to create a Query if you don't use it"]
+    #[inline]
+    pub fn map_err<E2: Error + 'static>(
+        self,
+        f: impl Fn(E) -> E2 + 'static,
+    ) -> Query<'link, P, R, E2>
+    where
+        R: 'static,
+        E: 'static,
+    {
+        let func = Rc::clone(&self.inner.func);
+        let f = Rc::new(f);
+        let opts = QueryOpts {
+            cache_time: self.inner.opts.cache_time,
+            network_mode: self.inner.opts.network_mode,
+            retry: SetOption::Inherrit,
+            cache_errors: self.inner.opts.cache_errors,
+            no_cache: self.inner.opts.no_cache,
+        };
+
+        Query::new_inner(
+            QueryFn::WithParam(Box::new(move |arg| {
+                let fut = func.call(arg);
+                let f = Rc::clone(&f);
+                Box::pin(async move { fut.await.map_err(&*f) })
+            })),
+            opts,
+            None,
+        )
+    }
+
     /// Create a new [`Query`] with an argument of type ``P``
     #[must_use = "No reason to create a Query if you don't use it"]
     #[inline]
     pub fn new_with_param(func: impl Fn(&P) -> QueryReturn<R, E> + 'link) -> Self {
-        Self::new_inner(QueryFn::WithParam(Box::new(func)), QueryOpts::new())
+        Self::new_inner(QueryFn::WithParam(Box::new(func)), QueryOpts::new(), None)
     }
 
     /// Create a new [`Query`] with an argument of type ``P``, with configuration options
@@ -233,7 +624,7 @@ impl<'link, P, R, E: Error> Query<'link, P, R, E> {
         func: impl Fn(&P) -> QueryReturn<R, E> + 'link,
         opts: impl Into<QueryOpts<'link, E>>,
     ) -> Self {
-        Self::new_inner(QueryFn::WithParam(Box::new(func)), opts.into())
+        Self::new_inner(QueryFn::WithParam(Box::new(func)), opts.into(), None)
     }
 
     /// Directly execute query without a client
@@ -246,13 +637,187 @@ impl<'link, P, R, E: Error> Query<'link, P, R, E> {
     }
 }
 
+/// A key identifying which data a [`KeyedQuery`] is for
+pub trait QueryKey: Hash + Eq + Clone + 'static {}
+
+impl<T: Hash + Eq + Clone + 'static> QueryKey for T {}
+
+/// A query function shared between keys, where each key is given its own cached data
+/// The key is passed to the query function as its argument
+pub struct KeyedQuery<'link, K, R, E> {
+    pub(crate) inner: Rc<KeyedQueryInner<'link, K, R, E>>,
+}
+
+pub(crate) struct KeyedQueryInner<'link, K, R, E> {
+    pub(crate) name: Option<String>,
+    base: Query<'link, K, R, E>,
+    pub(crate) keys: RefCell<HashMap<K, Query<'link, K, R, E>>>,
+}
+
+impl<K, R, E> Debug for KeyedQuery<'_, K, R, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedQuery")
+            .field("name", &self.inner.name)
+            .field("base", &self.inner.base)
+            .field("keys", &self.inner.keys.borrow().len())
+            .finish()
+    }
+}
+
+impl<'link, K: QueryKey, R, E: Error> KeyedQuery<'link, K, R, E> {
+    /// Create a new [`KeyedQuery`]
+    #[must_use = "No reason to create a KeyedQuery if you don't use it"]
+    #[inline]
+    pub fn new(func: impl Fn(&K) -> QueryReturn<R, E> + 'link) -> Self {
+        Self::new_inner(None, func, QueryOpts::new())
+    }
+
+    /// Create a new [`KeyedQuery`], with configuration options shared by every key
+    #[must_use = "No reason to create a KeyedQuery if you don't use it"]
+    #[inline]
+    pub fn new_with_opts(
+        func: impl Fn(&K) -> QueryReturn<R, E> + 'link,
+        opts: impl Into<QueryOpts<'link, E>>,
+    ) -> Self {
+        Self::new_inner(None, func, opts.into())
+    }
+
+    /// Create a new [`KeyedQuery`] with a name, which is given to predicates with its keys, see [`QueryDescriptor`]
+    #[must_use = "No reason to create a KeyedQuery if you don't use it"]
+    #[inline]
+    pub fn new_named(
+        name: impl Into<String>,
+        func: impl Fn(&K) -> QueryReturn<R, E> + 'link,
+    ) -> Self {
+        Self::new_inner(Some(name.into()), func, QueryOpts::new())
+    }
+
+    /// Create a new [`KeyedQuery`] with a name, with configuration options shared by every key
+    #[must_use = "No reason to create a KeyedQuery if you don't use it"]
+    #[inline]
+    pub fn new_named_with_opts(
+        name: impl Into<String>,
+        func: impl Fn(&K) -> QueryReturn<R, E> + 'link,
+        opts: impl Into<QueryOpts<'link, E>>,
+    ) -> Self {
+        Self::new_inner(Some(name.into()), func, opts.into())
+    }
+
+    fn new_inner(
+        name: Option<String>,
+        func: impl Fn(&K) -> QueryReturn<R, E> + 'link,
+        opts: QueryOpts<'link, E>,
+    ) -> Self {
+        Self {
+            inner: Rc::new(KeyedQueryInner {
+                name,
+                base: Query::new_with_param_and_opts(func, opts),
+                keys: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Get the [`Query`] for `key`, which should be fetched with `key` as its argument
+    /// The [`Query`] is created the first time `key` is used, and is kept for as long as this [`KeyedQuery`]
+    #[must_use = "Has no effect other than to get the query"]
+    pub fn query(&self, key: &K) -> Query<'link, K, R, E> {
+        self.inner
+            .keys
+            .borrow_mut()
+            .entry(key.clone())
+            .or_insert_with(|| Query::new_keyed(&self.inner.base))
+            .clone()
+    }
+}
+
+/// Type-erased description of a key of a [`KeyedQuery`], see [`crate::client::QueryClient::invalidate_where`]
+#[derive(Debug, Clone, Copy)]
+pub struct QueryDescriptor<'desc> {
+    pub(crate) name: Option<&'desc str>,
+    pub(crate) key: &'desc dyn Any,
+}
+
+impl<'desc> QueryDescriptor<'desc> {
+    /// Name of the [`KeyedQuery`], if it was given one
+    #[must_use = "Has no effect other than to get the name"]
+    #[inline]
+    pub const fn name(&self) -> Option<&'desc str> {
+        self.name
+    }
+
+    /// The key, if it's a ``K``
+    #[must_use = "Has no effect other than to get the key"]
+    #[inline]
+    pub fn key<K: QueryKey>(&self) -> Option<&'desc K> {
+        self.key.downcast_ref()
+    }
+}
+
+impl<P, R, E> QueryFn<'_, P, R, E> {
+    #[inline]
+    fn call(&self, arg: &P) -> QueryReturn<R, E> {
+        match *self {
+            Self::NoParam(ref func) => func(),
+            Self::WithParam(ref func) => func(arg),
+        }
+    }
+}
+
 impl<P, R, E> QueryInner<'_, P, R, E> {
+    /// See [`Query::id`], the same for the [`Query`] this is the inner of
+    pub(crate) fn id(&self) -> usize {
+        ptr::from_ref(self).cast::<()>() as usize
+    }
+
     #[inline]
     pub(crate) async fn execute_with_arg(&self, arg: &P) -> Result<R, E> {
-        match *self.func {
-            QueryFn::NoParam(ref func) => func(),
-            QueryFn::WithParam(ref func) => func(arg),
-        }
-        .await
+        self.func.call(arg).await
+    }
+
+    /// Whether the query function ignores the arg it's fetched with, so any fetch of it can join another in progress
+    pub(crate) fn takes_no_param(&self) -> bool {
+        matches!(*self.func, QueryFn::NoParam(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Query, QueryOpts};
+    use crate::config::{CacheTime, SetOption};
+
+    #[test]
+    fn debug_func_variant() {
+        let no_param = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let with_param =
+            Query::new_with_param(|&n: &i32| Box::pin(async move { Ok::<i32, ()>(n) }));
+
+        assert!(format!("{no_param:?}").contains("QueryFn::NoParam"));
+        assert!(format!("{with_param:?}").contains("QueryFn::WithParam"));
+    }
+
+    #[test]
+    fn id_shared_by_clones() {
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let other = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+
+        assert_eq!(query.id(), query.clone().id());
+        assert_ne!(query.id(), other.id());
+    }
+
+    #[test]
+    fn hydratable_with_opts() {
+        let query = Query::new(|| Box::pin(async { Ok::<i32, ()>(1_i32) }));
+        let hydratable = Query::new_hydratable(
+            &query,
+            "Hydratable".to_owned(),
+            QueryOpts::with_cache_time(CacheTime::Infinite),
+        );
+
+        assert!(matches!(
+            hydratable.inner.opts.cache_time,
+            SetOption::Set(CacheTime::Infinite)
+        ));
+        assert!(matches!(query.inner.opts.cache_time, SetOption::Inherrit));
+        assert_eq!(hydratable.inner.hydrate_key.as_deref(), Some("Hydratable"));
     }
 }
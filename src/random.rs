@@ -0,0 +1,21 @@
+/// Random number in `0.0..1.0`, from `Math.random()` in the browser
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn random() -> f64 {
+    js_sys::Math::random()
+}
+
+/// Random number in `0.0..1.0`, from the randomly seeded keys of std's hasher since nothing stronger is needed
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn random() -> f64 {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+
+    // Top 53 bits fit exactly in the mantissa
+    #[allow(clippy::cast_precision_loss)]
+    let random = (RandomState::new().build_hasher().finish() >> 11_u32) as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let max = (1_u64 << 53_u32) as f64;
+    random / max
+}
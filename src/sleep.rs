@@ -1,8 +1,44 @@
+use std::time::Duration;
+
 #[cfg(target_arch = "wasm32")]
 pub(crate) use wasm::sleep;
+#[cfg(target_arch = "wasm32")]
+use wasm::Clock;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use tokio::time::sleep;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::time::Instant as Clock;
+
+/// Monotonic time used for timestamps, the same type on every target
+/// Backed by [`tokio::time::Instant`] natively, & by `performance.now()` in the browser, where [`std::time::Instant`] isn't supported
+// Only `PartialEq` on every target, as the browser's time is a float
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Instant(Clock);
+
+impl Instant {
+    /// The current time
+    #[must_use = "Has no effect other than to get the time"]
+    #[inline]
+    pub fn now() -> Self {
+        Self(Clock::now())
+    }
+
+    /// Time passed since this instant
+    #[must_use = "Has no effect other than to get the time"]
+    #[inline]
+    pub fn elapsed(self) -> Duration {
+        self.0.elapsed()
+    }
+
+    /// Time passed from `earlier` to this instant, or zero if `earlier` is later
+    #[must_use = "Has no effect other than to get the duration"]
+    #[inline]
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        self.0.saturating_duration_since(earlier.0)
+    }
+}
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
@@ -16,6 +52,9 @@ mod wasm {
     extern "C" {
         #[wasm_bindgen(js_name = "setTimeout")]
         fn set_timeout(handler: Function, ms: u32) -> i32;
+
+        #[wasm_bindgen(js_namespace = performance, js_name = "now")]
+        fn performance_now() -> f64;
     }
 
     // For consistent return type between cfgs
@@ -35,4 +74,22 @@ mod wasm {
             .expect("should not fail");
         }
     }
+
+    /// Monotonic time in milliseconds, backed by `performance.now()`, see [`super::Instant`]
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub(super) struct Clock(f64);
+
+    impl Clock {
+        pub(super) fn now() -> Self {
+            Self(performance_now())
+        }
+
+        pub(super) fn elapsed(self) -> Duration {
+            Self::now().saturating_duration_since(self)
+        }
+
+        pub(super) fn saturating_duration_since(self, earlier: Self) -> Duration {
+            Duration::from_secs_f64((self.0 - earlier.0).max(0.0) / 1000.0)
+        }
+    }
 }
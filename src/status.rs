@@ -1,9 +1,8 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     error::Error as StdError,
     fmt::{self, Debug, Display, Formatter},
     rc::Rc,
-    sync::Arc,
 };
 
 #[cfg(feature = "serde")]
@@ -11,7 +10,10 @@ use serde::{Deserialize, Serialize};
 
 use tokio::sync::Notify;
 
-use crate::config::error::Error;
+use crate::config::{error::Error, NetworkMode};
+
+/// Monotonic time used for timestamps, such as [`QueryPeek::data_updated_at`]
+pub use crate::sleep::Instant;
 
 /// Fetch status of a Pending query
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,12 +23,29 @@ pub enum PendingStatus {
     Loading,
     /// See [`QueryStatus::Paused`]
     Paused,
+    /// See [`QueryStatus::Idle`], such as once a fetch is cancelled before there was any data
+    Idle,
+}
+
+thread_local! {
+    static DEFAULT_OVERRIDE: Cell<Option<PendingStatus>> = const { Cell::new(None) };
 }
 
 impl PendingStatus {
+    /// Override the status a [`QueryData::default()`] is created with on this thread, regardless of the connection
+    /// Pass `None` to go back to using the online status
+    #[inline]
+    pub fn set_default_override(status: Option<Self>) {
+        DEFAULT_OVERRIDE.with(|o| o.set(status));
+    }
+
     #[allow(unreachable_code, clippy::missing_const_for_fn)]
     #[inline]
     pub(crate) fn get() -> Self {
+        if let Some(status) = DEFAULT_OVERRIDE.with(Cell::get) {
+            return status;
+        }
+
         #[cfg(target_arch = "wasm32")]
         return match crate::browser::online_handler::is_online() {
             true => Self::Loading,
@@ -36,6 +55,15 @@ impl PendingStatus {
         Self::Loading
     }
 
+    /// As [`Self::get`], but without checking the connection for [`NetworkMode::Always`]
+    #[inline]
+    pub(crate) fn get_for(network_mode: NetworkMode) -> Self {
+        match network_mode {
+            NetworkMode::Always => DEFAULT_OVERRIDE.with(Cell::get).unwrap_or(Self::Loading),
+            NetworkMode::Online | NetworkMode::OfflineFirst => Self::get(),
+        }
+    }
+
     #[inline]
     pub(crate) const fn from_online(online: bool) -> Self {
         match online {
@@ -49,6 +77,17 @@ impl PendingStatus {
         match self {
             Self::Loading => QueryStatus::Loading,
             Self::Paused => QueryStatus::Paused,
+            Self::Idle => QueryStatus::Idle,
+        }
+    }
+
+    /// Inverse of [`Self::as_query`]
+    #[inline]
+    pub(crate) const fn from_query(status: QueryStatus) -> Self {
+        match status {
+            QueryStatus::Loading => Self::Loading,
+            QueryStatus::Paused => Self::Paused,
+            QueryStatus::Idle => Self::Idle,
         }
     }
 }
@@ -66,6 +105,14 @@ pub enum QueryStatus {
 }
 
 /// The status of a [`crate::query::Query`] for a specific [`crate::client::QueryClient`], and its data or error if appliciable
+///
+/// Fetching moves through these states:
+/// - With no data, [`Self::Pending`] with [`PendingStatus::Loading`], then the result with [`QueryStatus::Idle`]
+/// - With previous data, the previous result with [`QueryStatus::Loading`], then the new result with [`QueryStatus::Idle`]. See [`Self::is_previous_data`]
+/// - While waiting to retry, the latest error with [`QueryStatus::Loading`]
+/// - If cancelled before there was any data, [`Self::Pending`] with [`PendingStatus::Idle`]
+///
+/// Without an internet connection, [`PendingStatus::Paused`]/[`QueryStatus::Paused`] is used in place of `Loading` until the query is executed
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum QueryData<R, E> {
@@ -88,6 +135,67 @@ impl<R, E> Clone for QueryData<R, E> {
     }
 }
 
+/// Snapshot of the state of a query in a client's cache, see [`crate::client::QueryClient::peek`]
+#[derive(Debug)]
+pub struct QueryPeek<R, E> {
+    /// The cached data, `None` if the query isn't in the cache
+    pub data: Option<QueryData<R, E>>,
+    /// See [`QueryData::is_fetching`], false if the query isn't in the cache
+    pub is_fetching: bool,
+    /// Number of subscribers to the query
+    pub observer_count: usize,
+    /// When a fetch last succeeded, `None` if one hasn't yet
+    pub data_updated_at: Option<Instant>,
+    /// When a fetch last failed, after any retries, `None` if one hasn't yet
+    pub error_updated_at: Option<Instant>,
+}
+
+impl<R, E> Clone for QueryPeek<R, E> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            is_fetching: self.is_fetching,
+            observer_count: self.observer_count,
+            data_updated_at: self.data_updated_at,
+            error_updated_at: self.error_updated_at,
+        }
+    }
+}
+
+impl<R, E> QueryData<R, E> {
+    /// Whether the query is being executed, or will be once there is an internet connection
+    #[must_use = "Has no effect if unused"]
+    #[inline]
+    pub const fn is_fetching(&self) -> bool {
+        !matches!(
+            *self,
+            Self::Pending(PendingStatus::Idle)
+                | Self::Ok(_, QueryStatus::Idle)
+                | Self::Err(_, QueryStatus::Idle)
+        )
+    }
+
+    /// Whether the last fetch to finish failed, including while the query is fetched again
+    /// Becomes false once a fetch succeeds, which replaces the error
+    #[must_use = "Has no effect if unused"]
+    #[inline]
+    pub const fn had_error(&self) -> bool {
+        matches!(*self, Self::Err(..))
+    }
+
+    /// Whether the data or error is from a previous fetch, kept while the query is fetched again
+    #[must_use = "Has no effect if unused"]
+    #[inline]
+    pub const fn is_previous_data(&self) -> bool {
+        matches!(
+            *self,
+            Self::Ok(_, QueryStatus::Loading | QueryStatus::Paused)
+                | Self::Err(_, QueryStatus::Loading | QueryStatus::Paused)
+        )
+    }
+}
+
 impl<R, E> Default for QueryData<R, E> {
     #[inline]
     fn default() -> Self {
@@ -98,43 +206,55 @@ impl<R, E> Default for QueryData<R, E> {
 /// The status of a [`crate::mutation::Mutation`] for a specific [`crate::client::QueryClient`], and its data or error if applicable
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub enum MutationData<R, E> {
+pub enum MutationData<P, R, E> {
     /// Mutation has not been initiated
     Idle,
-    /// Mutation has been initiated and is executing
-    Loading,
+    /// Mutation has been initiated and is executing, with the variables it was given if known, such as for showing them optimistically
+    Loading(Option<Rc<P>>),
     /// Mutation was successful
     Ok(Rc<R>),
     /// Mutation returned an error
     Err(MutateError<E>),
 }
 
-impl<R, E> Default for MutationData<R, E> {
+impl<P, R, E> Default for MutationData<P, R, E> {
     fn default() -> Self {
         Self::Idle
     }
 }
 
-impl<R, E> Clone for MutationData<R, E> {
+impl<P, R, E> Clone for MutationData<P, R, E> {
     fn clone(&self) -> Self {
         match *self {
             Self::Idle => Self::Idle,
-            Self::Loading => Self::Loading,
+            Self::Loading(ref v) => Self::Loading(v.clone()),
             Self::Ok(ref o) => Self::Ok(Rc::clone(o)),
             Self::Err(ref e) => Self::Err(e.clone()),
         }
     }
 }
 
+impl<P, R, E> MutationData<P, R, E> {
+    /// The variables the mutation was given, while it's [`Self::Loading`]
+    #[must_use = "Has no effect other than to get the variables"]
+    #[inline]
+    pub const fn variables(&self) -> Option<&Rc<P>> {
+        match *self {
+            Self::Loading(ref variables) => variables.as_ref(),
+            Self::Idle | Self::Ok(_) | Self::Err(_) => None,
+        }
+    }
+}
+
 /// Result of awaiting on a [`NoConnection`]
 #[derive(Debug)]
 pub enum FetchResultWaited<R, E> {
     /// See [`FetchResult::Fresh`]
     Fresh(Result<Rc<R>, Rc<E>>),
     /// See [`FetchResult::Stale`]
-    Stale(Result<R, E>),
+    Stale(Result<Rc<R>, Rc<E>>),
     /// See [`FetchResult::Cancelled`]
-    Cancelled,
+    Cancelled(CancelReason),
 }
 
 /// See [`FetchResult::NoConnection`]
@@ -162,6 +282,16 @@ impl<R, E> NoConnection<R, E> {
             self.inner.notify.notified().await;
         }
     }
+
+    /// Gets the result of the query if connection has returned & it's finished, without waiting
+    ///
+    /// # Errors
+    /// Gives back `self` if the result isn't available yet, so it can be checked again or waited on
+    #[inline]
+    pub fn try_get(self) -> Result<FetchResultWaited<R, E>, Self> {
+        let result = self.inner.result.borrow_mut().take();
+        result.ok_or(self)
+    }
 }
 
 /// Result of a direct call to a fetch method, notifying about whether the resulting data is the latest stored in the client
@@ -170,18 +300,58 @@ pub enum FetchResult<R, E> {
     /// Query was the latest to be initiated in the time it took to complete
     Fresh(Result<Rc<R>, Rc<E>>),
     /// Another query was initiated in the time it took for this query to complete
-    Stale(Result<R, E>),
+    Stale(Result<Rc<R>, Rc<E>>),
     /// There was no internet connection when this query was initiated
     NoConnection(NoConnection<R, E>),
     /// Another query was initated, or this query was cancelled in the time it took to retry this query
-    Cancelled,
+    Cancelled(CancelReason),
+}
+
+/// Why a fetch was [`FetchResult::Cancelled`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CancelReason {
+    /// The cached entry was replaced by a newer one while retrying, so the result would be stale
+    Superseded,
+    /// The fetch was cancelled by the caller, see [`QueryClient::fetch_with_cancel`](crate::client::QueryClient::fetch_with_cancel)
+    Explicit,
+    /// The cached entry was removed, or every clone of the client was dropped, before the fetch could complete
+    Evicted,
+}
+
+/// Snapshot of a [`FetchResult`] that can be serialized, such as for logging
+/// [`FetchResult::NoConnection`] can't be, so only records that there was no connection
+#[cfg(feature = "serde")]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum FetchOutcome<R, E> {
+    /// See [`FetchResult::Fresh`]
+    Fresh(Result<Rc<R>, Rc<E>>),
+    /// See [`FetchResult::Stale`]
+    Stale(Result<Rc<R>, Rc<E>>),
+    /// See [`FetchResult::NoConnection`]
+    NoConnection,
+    /// See [`FetchResult::Cancelled`]
+    Cancelled(CancelReason),
+}
+
+#[cfg(feature = "serde")]
+impl<R, E> From<FetchResult<R, E>> for FetchOutcome<R, E> {
+    fn from(value: FetchResult<R, E>) -> Self {
+        match value {
+            FetchResult::Fresh(f) => Self::Fresh(f),
+            FetchResult::Stale(s) => Self::Stale(s),
+            FetchResult::NoConnection(_) => Self::NoConnection,
+            FetchResult::Cancelled(r) => Self::Cancelled(r),
+        }
+    }
 }
 
 /// Error of a direct call to a mutate method
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MutateError<E> {
     /// The mutation function returned an error ``E``
-    FnError(Arc<E>),
+    /// The same [`Rc`] is given to the mutation's callbacks & [`crate::config::retry::RetryConfig`] functions, `E` needn't be [`Clone`] to share it
+    FnError(Rc<E>),
     /// There was no internet connection when this mutation was initiated
     NoConnection,
 }
@@ -212,8 +382,65 @@ impl<E: Debug> Debug for MutateError<E> {
 impl<E> Clone for MutateError<E> {
     fn clone(&self) -> Self {
         match *self {
-            Self::FnError(ref e) => Self::FnError(Arc::clone(e)),
+            Self::FnError(ref e) => Self::FnError(Rc::clone(e)),
             Self::NoConnection => Self::NoConnection,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PendingStatus, QueryData};
+
+    #[test]
+    fn default_override() {
+        PendingStatus::set_default_override(Some(PendingStatus::Paused));
+        assert!(matches!(
+            QueryData::<(), ()>::default(),
+            QueryData::Pending(PendingStatus::Paused)
+        ));
+
+        PendingStatus::set_default_override(None);
+        assert!(matches!(
+            QueryData::<(), ()>::default(),
+            QueryData::Pending(PendingStatus::Loading)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fetch_outcome_serialize() {
+        use std::{cell::RefCell, rc::Rc};
+
+        use tokio::sync::Notify;
+
+        use super::{CancelReason, FetchOutcome, FetchResult, NoConnection, NoConnectionInner};
+
+        let json = |result: FetchResult<i32, String>| {
+            serde_json::to_string(&FetchOutcome::from(result)).expect("outcome should serialize")
+        };
+        let no_conn = NoConnection {
+            inner: Rc::new(NoConnectionInner {
+                result: RefCell::new(None),
+                notify: Notify::new(),
+            }),
+        };
+
+        assert_eq!(
+            json(FetchResult::Fresh(Ok(Rc::new(1_i32)))),
+            r#"{"Fresh":{"Ok":1}}"#
+        );
+        assert_eq!(
+            json(FetchResult::Stale(Err(Rc::new("failed".to_owned())))),
+            r#"{"Stale":{"Err":"failed"}}"#
+        );
+        assert_eq!(
+            json(FetchResult::NoConnection(no_conn)),
+            r#""NoConnection""#
+        );
+        assert_eq!(
+            json(FetchResult::Cancelled(CancelReason::Explicit)),
+            r#"{"Cancelled":"Explicit"}"#
+        );
+    }
+}
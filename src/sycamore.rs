@@ -10,16 +10,17 @@
     )
 )]
 
-use std::{mem, rc::Rc};
+use std::{future::Future, mem, rc::Rc, time::Duration};
 
 use sycamore::prelude::*;
+use tokio::sync::Notify;
 
 use crate::{
     client::QueryClient,
     config::error::Error,
     mutation::{Mutation, MutationCallbacks},
     query::Query,
-    status::{MutateError, MutationData, QueryData},
+    status::{MutateError, MutationData, QueryData, QueryStatus},
 };
 
 /// Type of a raw pointer to a client, so it can be identified by sycamore's context system
@@ -101,16 +102,58 @@ fn use_query_inner<'scope, P, R, E: Error>(
     arg: P,
 ) -> &'scope Signal<QueryData<R, E>> {
     #[cfg(target_arch = "wasm32")]
-    {
-        use sycamore::futures;
+    subscribe_and_fetch(cx, use_query_client(cx), data_signal, query, arg);
+    data_signal
+}
+
+/// Subscribe `data_signal` to the query & fetch it, for as long as `cx` is alive
+/// `cx` may be a child of the scope the client, query & signal belong to, so the subscription can end before they do
+#[cfg(target_arch = "wasm32")]
+fn subscribe_and_fetch<'scope, 'child, P, R, E: Error>(
+    cx: Scope<'child>,
+    client: &'scope QueryClient<'scope>,
+    data_signal: &'scope Signal<QueryData<R, E>>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+) where
+    'scope: 'child,
+{
+    use sycamore::futures;
+
+    let guard = client.subscribe_query(query, |data| {
+        data_signal.set(data);
+    });
+    create_ref(cx, guard);
+    futures::spawn_local_scoped(cx, async move {
+        client.fetch_with_arg(query, arg).await;
+    });
+}
 
+/// Use a query that's only subscribed to & fetched while `enabled` is true, returning a reactive signal of the status & result
+///
+/// Any cached data is used straight away, even while disabled
+/// Once `enabled` becomes false, the signal is unsubscribed & any fetch in progress for it is cancelled, but keeps the data it last had
+#[must_use = "If you don't need the query result, consider QueryClient::prefetch"]
+#[inline]
+pub fn use_query_enabled<'scope, P: Clone + 'scope, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+    enabled: &'scope Signal<bool>,
+) -> &'scope Signal<QueryData<R, E>> {
+    let data_signal = create_signal(cx, QueryData::default());
+    #[cfg(target_arch = "wasm32")]
+    {
         let client = use_query_client(cx);
-        let guard = client.subscribe_query(query, |data| {
+        if let Some(data) = client.query_data(query) {
             data_signal.set(data);
-        });
-        create_ref(cx, guard);
-        futures::spawn_local_scoped(cx, async {
-            client.fetch_with_arg(query, arg).await;
+        }
+
+        // Child scope is disposed each time `enabled` changes, dropping the subscription & aborting the fetch
+        create_effect_scoped(cx, move |cx| {
+            if *enabled.get() {
+                subscribe_and_fetch(cx, client, data_signal, query, arg.clone());
+            }
         });
     }
     data_signal
@@ -149,12 +192,197 @@ pub fn use_query_with_signal_arg<'scope, P: Clone, R, E: Error>(
     data_signal
 }
 
+/// Use a query with a signal as the arg, only fetching once its value hasn't changed for `dedup_window`
+///
+/// The signal is subscribed & given any cached data straight away, only the fetch waits out the window, using a clone of the arg
+/// Each change cancels the fetch for the previous value, whether it's still waiting out the window or already in progress
+#[must_use = "If you don't need the query result, consider QueryClient::prefetch"]
+#[inline]
+pub fn use_query_with_signal_arg_dedup<'scope, P: Clone + 'scope, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: &'scope Signal<P>,
+    dedup_window: Duration,
+) -> &'scope Signal<QueryData<R, E>> {
+    let data_signal = create_signal(cx, QueryData::default());
+    #[cfg(target_arch = "wasm32")]
+    {
+        use sycamore::futures;
+
+        use crate::sleep;
+
+        let client = use_query_client(cx);
+        if let Some(data) = client.query_data(query) {
+            data_signal.set(data);
+        }
+        let guard = client.subscribe_query(query, |data| {
+            data_signal.set(data);
+        });
+        create_ref(cx, guard);
+
+        // Child scope is disposed each time `arg` changes, aborting the wait or the fetch
+        create_effect_scoped(cx, move |cx| {
+            let arg = arg.get().as_ref().clone();
+            futures::spawn_local_scoped(cx, async move {
+                sleep::sleep(dedup_window).await;
+                client.fetch_with_arg(query, arg).await;
+            });
+        });
+    }
+    data_signal
+}
+
+/// Use a query, suspending the nearest [`Suspense`](sycamore::suspense::Suspense) boundary while there's no data yet
+///
+/// Suspends until the first [`QueryData::Ok`] or [`QueryData::Err`], or until the fetch is cancelled
+/// Data that's already cached is used straight away, without suspending
+#[must_use = "If you don't need the query result, consider QueryClient::prefetch"]
+#[inline]
+pub fn use_query_suspense<'scope, P, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+) -> &'scope Signal<QueryData<R, E>> {
+    let data_signal = create_signal(cx, QueryData::default());
+    #[cfg(target_arch = "wasm32")]
+    {
+        use sycamore::suspense;
+
+        let client = use_query_client(cx);
+        if let Some(data) = client.query_data(query) {
+            data_signal.set(data);
+        }
+        if matches!(*data_signal.get_untracked(), QueryData::Pending(_)) {
+            let notify = notify_on_change(cx, data_signal);
+            suspense::suspense_scope(cx, async move {
+                // Stop suspending if the fetch is cancelled, as nothing more will arrive
+                while data_signal.get_untracked().is_fetching()
+                    && matches!(*data_signal.get_untracked(), QueryData::Pending(_))
+                {
+                    notify.notified().await;
+                }
+            });
+        }
+        subscribe_and_fetch(cx, client, data_signal, query, arg);
+    }
+    data_signal
+}
+
+/// Notified each time `data` changes
+/// Stores a permit if nothing is waiting yet, so a change can't be missed
+fn notify_on_change<'scope, T>(cx: Scope<'scope>, data: &'scope Signal<T>) -> &'scope Notify {
+    let notify = create_ref(cx, Notify::new());
+    create_effect(cx, move || {
+        data.track();
+        notify.notify_one();
+    });
+    notify
+}
+
+/// Use a query, returning a future that completes once its data is resolved
+///
+/// Resolved meaning [`QueryData::Ok`] or [`QueryData::Err`] with nothing left in progress, so data that's being refetched or retried isn't resolved
+/// Useful for awaiting query results in event handlers
+///
+/// # Errors
+/// The future resolves to an error if the query function does, once out of retries
+#[inline]
+pub fn use_query_resolved<'scope, P, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+) -> impl Future<Output = Result<Rc<R>, Rc<E>>> + 'scope {
+    let data = use_query_with_arg(cx, query, arg);
+    let notify = notify_on_change(cx, data);
+
+    async move {
+        loop {
+            let resolved = match *data.get_untracked() {
+                QueryData::Ok(ref r, QueryStatus::Idle) => Some(Ok(Rc::clone(r))),
+                QueryData::Err(ref e, QueryStatus::Idle) => Some(Err(Rc::clone(e))),
+                QueryData::Pending(_) | QueryData::Ok(..) | QueryData::Err(..) => None,
+            };
+            match resolved {
+                Some(res) => return res,
+                None => notify.notified().await,
+            }
+        }
+    }
+}
+
+/// Represents a query for the current [`QueryClient`] in scope when a variant of `use_query_with_refetch` is called
+#[derive(Debug)]
+pub struct UseQuery<'scope, P, R, E> {
+    cx: Scope<'scope>,
+    client: &'scope QueryClient<'scope>,
+    data: &'scope Signal<QueryData<R, E>>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+}
+
+impl<'scope, P: Clone, R, E: Error> UseQuery<'scope, P, R, E> {
+    /// Get the data for this query
+    #[must_use = "Has no effect other than to get the data"]
+    pub const fn data(&self) -> &'scope Signal<QueryData<R, E>> {
+        self.data
+    }
+
+    /// Fetch the query again with the same argument, in the background
+    ///
+    /// The fetch is cancelled if the scope is disposed before it completes
+    pub fn refetch(&'scope self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            use sycamore::futures;
+
+            futures::spawn_local_scoped(self.cx, async {
+                self.client
+                    .fetch_with_arg(self.query, self.arg.clone())
+                    .await;
+            });
+        }
+    }
+}
+
+/// Use a query, returning its reactive signal along with a way to manually refetch it, see [`UseQuery`]
+#[must_use = "If you don't need the query result, consider QueryClient::prefetch"]
+#[inline]
+pub fn use_query_with_refetch<'scope, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, (), R, E>,
+) -> &'scope UseQuery<'scope, (), R, E> {
+    use_query_with_arg_and_refetch(cx, query, ())
+}
+
+/// As [`use_query_with_refetch`], for a query that takes an argument
+///
+/// Refetching uses a clone of `arg`
+#[must_use = "If you don't need the query result, consider QueryClient::prefetch"]
+#[inline]
+pub fn use_query_with_arg_and_refetch<'scope, P: Clone, R, E: Error>(
+    cx: Scope<'scope>,
+    query: &'scope Query<'scope, P, R, E>,
+    arg: P,
+) -> &'scope UseQuery<'scope, P, R, E> {
+    let data = use_query_with_arg(cx, query, arg.clone());
+    create_ref(
+        cx,
+        UseQuery {
+            cx,
+            client: use_query_client(cx),
+            data,
+            query,
+            arg,
+        },
+    )
+}
+
 /// Represents a mutation for the current [`QueryClient`] in scope when a variant of `use_mutation` is called
 #[derive(Debug)]
 pub struct UseMutation<'scope, P, R, E, C> {
     cx: Scope<'scope>,
     client: &'scope QueryClient<'scope>,
-    data: &'scope Signal<MutationData<R, E>>,
+    data: &'scope Signal<MutationData<P, R, E>>,
     mutation: &'scope Mutation<'scope, P, R, E>,
     callbacks: Option<MutationCallbacks<P, R, E, C>>,
 }
@@ -162,7 +390,7 @@ pub struct UseMutation<'scope, P, R, E, C> {
 impl<'link, P, R, E, C> UseMutation<'link, P, R, E, C> {
     /// Get the data for this mutation
     #[must_use = "Has no effect other than to get the data"]
-    pub const fn data(&self) -> &'link Signal<MutationData<R, E>> {
+    pub const fn data(&self) -> &'link Signal<MutationData<P, R, E>> {
         self.data
     }
 
@@ -257,3 +485,265 @@ pub fn use_mutation_with_callbacks<'scope, P, R, E, C>(
 ) -> &'scope UseMutation<'scope, P, R, E, C> {
     use_mutation_inner(cx, mutation, Some(callbacks))
 }
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Duration,
+    };
+
+    use sycamore::{prelude::*, suspense::await_suspense};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{
+        provide_query_client, use_query_enabled, use_query_resolved, use_query_suspense,
+        use_query_with_refetch, use_query_with_signal_arg_dedup,
+    };
+    use crate::{client::QueryClient, query::Query, sleep, status::QueryData};
+
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    async fn settle() {
+        sleep::sleep(Duration::from_millis(10)).await;
+    }
+
+    #[wasm_bindgen_test]
+    async fn enabled_toggle() {
+        let count = Rc::new(Cell::new(0_i32));
+        let enabled = create_rc_signal(false);
+
+        let disposer = create_scope({
+            let count = Rc::clone(&count);
+            let enabled = enabled.clone();
+            move |cx| {
+                provide_query_client(cx, QueryClient::default());
+                let query = create_ref(
+                    cx,
+                    Query::new(move || {
+                        count.set(count.get() + 1_i32);
+                        Box::pin(async { Ok::<i32, ()>(1_i32) })
+                    }),
+                );
+                let enabled = create_ref(cx, enabled);
+                let _data = use_query_enabled(cx, query, (), enabled);
+            }
+        });
+
+        settle().await;
+        assert_eq!(count.get(), 0_i32);
+
+        enabled.set(true);
+        settle().await;
+        assert_eq!(count.get(), 1_i32);
+
+        enabled.set(false);
+        settle().await;
+        assert_eq!(count.get(), 1_i32);
+
+        enabled.set(true);
+        settle().await;
+        assert_eq!(count.get(), 2_i32);
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    #[wasm_bindgen_test]
+    async fn refetch() {
+        let count = Rc::new(Cell::new(0_i32));
+        let refetches = create_rc_signal(0_i32);
+
+        let disposer = create_scope({
+            let count = Rc::clone(&count);
+            let refetches = refetches.clone();
+            move |cx| {
+                provide_query_client(cx, QueryClient::default());
+                let query = create_ref(
+                    cx,
+                    Query::new(move || {
+                        count.set(count.get() + 1_i32);
+                        Box::pin(async { Ok::<i32, ()>(1_i32) })
+                    }),
+                );
+                let use_query = use_query_with_refetch(cx, query);
+                create_effect(cx, move || {
+                    if *refetches.get() > 0_i32 {
+                        use_query.refetch();
+                    }
+                });
+            }
+        });
+
+        settle().await;
+        assert_eq!(count.get(), 1_i32);
+
+        refetches.set(1_i32);
+        settle().await;
+        assert_eq!(count.get(), 2_i32);
+
+        refetches.set(2_i32);
+        settle().await;
+        assert_eq!(count.get(), 3_i32);
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    #[wasm_bindgen_test]
+    async fn resolved() {
+        let (tx, rx) = futures::channel::oneshot::channel();
+
+        let disposer = create_scope(move |cx| {
+            provide_query_client(cx, QueryClient::default());
+            let query = create_ref(
+                cx,
+                Query::new(|| {
+                    Box::pin(async {
+                        sleep::sleep(Duration::from_millis(5)).await;
+                        Ok::<i32, ()>(1_i32)
+                    })
+                }),
+            );
+            let resolved = use_query_resolved(cx, query, ());
+            sycamore::futures::spawn_local_scoped(cx, async move {
+                drop(tx.send(resolved.await));
+            });
+        });
+
+        let res = rx.await.expect("scope should still be alive");
+        assert!(matches!(res, Ok(d) if *d == 1_i32));
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    /// Scope awaiting a suspense boundary around `use_query_suspense`, with whether the boundary has resolved
+    fn suspense_scope(cached: bool) -> (ScopeDisposer<'static>, Rc<Cell<bool>>) {
+        let resolved = Rc::new(Cell::new(false));
+        let disposer = create_scope({
+            let resolved = Rc::clone(&resolved);
+            move |cx| {
+                let client = provide_query_client(cx, QueryClient::default());
+                let query = create_ref(
+                    cx,
+                    Query::new(|| {
+                        Box::pin(async {
+                            sleep::sleep(Duration::from_millis(20)).await;
+                            Ok::<i32, ()>(1_i32)
+                        })
+                    }),
+                );
+                if cached {
+                    client.set_query_data(query, 0_i32);
+                }
+                sycamore::futures::spawn_local_scoped(cx, async move {
+                    let data =
+                        await_suspense(cx, async { use_query_suspense(cx, query, ()) }).await;
+                    assert!(matches!(*data.get_untracked(), QueryData::Ok(..)));
+                    resolved.set(true);
+                });
+            }
+        });
+        (disposer, resolved)
+    }
+
+    #[wasm_bindgen_test]
+    async fn suspense_until_resolved() {
+        let (disposer, resolved) = suspense_scope(false);
+
+        sleep::sleep(Duration::from_millis(5)).await;
+        assert!(!resolved.get());
+        sleep::sleep(Duration::from_millis(40)).await;
+        assert!(resolved.get());
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    #[wasm_bindgen_test]
+    async fn suspense_cached() {
+        let (disposer, resolved) = suspense_scope(true);
+
+        sleep::sleep(Duration::from_millis(5)).await;
+        assert!(resolved.get());
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    #[wasm_bindgen_test]
+    async fn signal_arg_dedup() {
+        let fetched = Rc::new(RefCell::new(Vec::new()));
+        let arg = create_rc_signal(0_i32);
+
+        let disposer = create_scope({
+            let fetched = Rc::clone(&fetched);
+            let arg = arg.clone();
+            move |cx| {
+                provide_query_client(cx, QueryClient::default());
+                let query = create_ref(
+                    cx,
+                    Query::new_with_param(move |&arg: &i32| {
+                        fetched.borrow_mut().push(arg);
+                        Box::pin(async move { Ok::<i32, ()>(arg) })
+                    }),
+                );
+                let arg = create_ref(cx, arg);
+                let _data =
+                    use_query_with_signal_arg_dedup(cx, query, arg, Duration::from_millis(20));
+            }
+        });
+
+        for i in 1_i32..=3_i32 {
+            arg.set(i);
+            sleep::sleep(Duration::from_millis(2)).await;
+        }
+        sleep::sleep(Duration::from_millis(40)).await;
+        assert_eq!(*fetched.borrow(), [3_i32]);
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+
+    #[wasm_bindgen_test]
+    async fn signal_arg_dedup_uses_cache() {
+        let fetched = Rc::new(Cell::new(0_i32));
+        let seen = Rc::new(Cell::new(None));
+
+        let disposer = create_scope({
+            let fetched = Rc::clone(&fetched);
+            let seen = Rc::clone(&seen);
+            move |cx| {
+                let client = provide_query_client(cx, QueryClient::default());
+                let query = create_ref(
+                    cx,
+                    Query::new_with_param(move |&arg: &i32| {
+                        fetched.set(fetched.get() + 1_i32);
+                        Box::pin(async move { Ok::<i32, ()>(arg) })
+                    }),
+                );
+                client.set_query_data(query, 5_i32);
+                let arg = create_signal(cx, 0_i32);
+                let data =
+                    use_query_with_signal_arg_dedup(cx, query, arg, Duration::from_millis(20));
+                create_effect(cx, move || {
+                    if let QueryData::Ok(ref d, _) = *data.get() {
+                        seen.set(Some(**d));
+                    }
+                });
+            }
+        });
+
+        // Cached data is shown without waiting out the window, only the fetch waits
+        assert_eq!(seen.get(), Some(5_i32));
+        assert_eq!(fetched.get(), 0_i32);
+        sleep::sleep(Duration::from_millis(40)).await;
+        assert_eq!(fetched.get(), 1_i32);
+        assert_eq!(seen.get(), Some(0_i32));
+
+        // SAFETY: Nothing borrowed from the scope outlives it
+        unsafe { disposer.dispose() };
+    }
+}
@@ -0,0 +1,42 @@
+use std::{future::Future, time::Duration};
+
+use tokio::{
+    runtime::{Builder, Runtime},
+    task::{self, LocalSet},
+    time,
+};
+
+thread_local! {
+    /// Shared by each [`block_on`] on this thread, so tasks spawned by one keep running in the next
+    static LOCAL: (Runtime, LocalSet) = (paused_runtime(), LocalSet::new());
+}
+
+fn paused_runtime() -> Runtime {
+    Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .expect("should be able to build test runtime")
+}
+
+/// Run `fut` to completion on a current-thread runtime inside a [`LocalSet`], so `spawn_local` works as it does in the browser
+/// Time starts paused, use [`advance`] to move it forward
+pub(crate) fn run_local<F: Future>(fut: F) -> F::Output {
+    LocalSet::new().block_on(&paused_runtime(), fut)
+}
+
+/// As [`run_local`], but on a runtime & [`LocalSet`] that last for the whole thread, rather than just `fut`
+///
+/// # Panics
+/// Will panic if called from within `fut`
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    LOCAL.with(|local| local.1.block_on(&local.0, fut))
+}
+
+/// Move paused time forward by `dur`, then let any tasks woken by it run
+/// Newly spawned tasks are run first, so timers they start are included
+pub(crate) async fn advance(dur: Duration) {
+    task::yield_now().await;
+    time::advance(dur).await;
+    task::yield_now().await;
+}
@@ -0,0 +1,35 @@
+use std::{future::Future, rc::Rc};
+
+use tracing::{Instrument, Span};
+
+use crate::status::{FetchResult, MutateError};
+
+/// Runs `fut` within `span`, recording the `outcome` of its output once it completes
+pub(crate) async fn in_span<F: Future>(
+    span: Span,
+    fut: F,
+    outcome: impl FnOnce(&F::Output) -> &'static str,
+) -> F::Output {
+    let record = span.clone();
+    let output = fut.instrument(span).await;
+    record.record("outcome", outcome(&output));
+    output
+}
+
+pub(crate) const fn fetch_outcome<R, E>(result: &FetchResult<R, E>) -> &'static str {
+    match *result {
+        FetchResult::Fresh(Ok(_)) => "ok",
+        FetchResult::Fresh(Err(_)) => "err",
+        FetchResult::Stale(_) => "stale",
+        FetchResult::NoConnection(_) => "no_connection",
+        FetchResult::Cancelled(_) => "cancelled",
+    }
+}
+
+pub(crate) const fn mutate_outcome<R, E>(result: &Result<Rc<R>, MutateError<E>>) -> &'static str {
+    match *result {
+        Ok(_) => "ok",
+        Err(MutateError::FnError(_)) => "err",
+        Err(MutateError::NoConnection) => "no_connection",
+    }
+}
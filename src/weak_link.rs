@@ -2,7 +2,7 @@ use std::{
     cell::{Ref, RefCell},
     collections::{
         hash_map::{Entry as HashMapEntry, OccupiedEntry as HashMapOccupied},
-        HashMap, HashSet,
+        HashMap, HashSet, VecDeque,
     },
     rc::{Rc, Weak},
 };
@@ -90,18 +90,19 @@ impl<'link, T> WeakLink<'link, T> {
         Self {
             inner: Rc::new(WeakLinkInner {
                 targets: RefCell::new(HashMap::new()),
+                deferred: RefCell::new(VecDeque::new()),
             }),
         }
     }
 
-    fn link(&self, target: &Target<'link>) -> HashWeakPtr<TargetInner<'link>> {
+    fn link(&self, target: &Rc<TargetInner<'link>>) -> HashWeakPtr<TargetInner<'link>> {
         // Non-trivial
         #[allow(trivial_casts)]
-        target.inner.links.borrow_mut().insert(HashWeakPtr(
+        target.links.borrow_mut().insert(HashWeakPtr(
             Rc::downgrade(&self.inner) as Weak<dyn WeakLinkFrom<'link>>
         ));
 
-        HashWeakPtr(Rc::downgrade(&target.inner))
+        HashWeakPtr(Rc::downgrade(target))
     }
 
     pub(crate) fn with_entry<R>(
@@ -109,12 +110,16 @@ impl<'link, T> WeakLink<'link, T> {
         target: &Target<'link>,
         f: impl FnOnce(Entry<'_, 'link, T>) -> R,
     ) -> R {
-        let mut targets = self.inner.targets.borrow_mut();
-        let entry = targets.entry(self.link(target));
-        f(match entry {
-            HashMapEntry::Occupied(o) => Entry::Occupied(OccupiedEntry { entry: o }),
-            HashMapEntry::Vacant(_v) => Entry::Vacant, /*(VacantEntry { _entry: v })*/
-        })
+        let ret = {
+            let mut targets = self.inner.targets.borrow_mut();
+            let entry = targets.entry(self.link(&target.inner));
+            f(match entry {
+                HashMapEntry::Occupied(o) => Entry::Occupied(OccupiedEntry { entry: o }),
+                HashMapEntry::Vacant(_v) => Entry::Vacant, /*(VacantEntry { _entry: v })*/
+            })
+        };
+        self.run_deferred();
+        ret
     }
 
     pub(crate) fn with_or_else<R>(
@@ -122,12 +127,63 @@ impl<'link, T> WeakLink<'link, T> {
         target: &Target<'link>,
         default: impl FnOnce() -> T,
         f: impl FnOnce(&mut T) -> R,
+    ) -> R {
+        let ret = self.with_or_else_inner(&target.inner, default, f);
+        self.run_deferred();
+        ret
+    }
+
+    /// As [`Self::with_or_else`], but if the link is already borrowed further up the stack, such as by whatever notified the listener calling this,
+    /// the call is deferred until that borrow is released instead of panicking
+    pub(crate) fn with_or_else_deferred(
+        &self,
+        target: &Target<'link>,
+        default: impl FnOnce() -> T + 'link,
+        f: impl FnOnce(&mut T) + 'link,
+    ) {
+        if self.inner.targets.try_borrow_mut().is_ok() {
+            self.with_or_else(target, default, f);
+            return;
+        }
+
+        let target = Rc::downgrade(&target.inner);
+        self.inner
+            .deferred
+            .borrow_mut()
+            .push_back(Box::new(move |this| {
+                // Nothing to do if the target was dropped in the meantime
+                if let Some(target) = target.upgrade() {
+                    this.with_or_else_inner(&target, default, f);
+                }
+            }));
+    }
+
+    fn with_or_else_inner<R>(
+        &self,
+        target: &Rc<TargetInner<'link>>,
+        default: impl FnOnce() -> T,
+        f: impl FnOnce(&mut T) -> R,
     ) -> R {
         let mut targets = self.inner.targets.borrow_mut();
         let value = targets.entry(self.link(target)).or_insert_with(default);
         f(value)
     }
 
+    /// Runs calls deferred by [`Self::with_or_else_deferred`], if the link is no longer borrowed
+    /// Each may defer more, so keeps going until there are none left
+    fn run_deferred(&self) {
+        if self.inner.targets.try_borrow_mut().is_err() {
+            return;
+        }
+
+        loop {
+            let Some(deferred) = self.inner.deferred.borrow_mut().pop_front() else {
+                break;
+            };
+            deferred(self);
+        }
+    }
+
     /// Borrows value from the interal [`RefCell`] of the [`WeakLink`] for the link between it and a [`Target`]
     /// Returns None if no link has been made between the two
     pub(crate) fn borrow(&self, target: &Target<'link>) -> Option<Ref<'_, T>> {
@@ -136,14 +192,38 @@ impl<'link, T> WeakLink<'link, T> {
         })
         .ok()
     }
+
+    /// Removes the link to every [`Target`], returning the values that were stored for them
+    // Nothing needs every value at once yet, the online handler waits for the connection itself rather than queueing retries here
+    #[allow(dead_code)]
+    pub(crate) fn drain(&self) -> Vec<T> {
+        let targets = self.inner.targets.take();
+        targets
+            .into_iter()
+            .map(|(target, value)| {
+                if let Some(target) = target.upgrade() {
+                    // Non-trivial
+                    #[allow(trivial_casts)]
+                    target.links.borrow_mut().remove(&HashWeakPtr(
+                        Rc::downgrade(&self.inner) as Weak<dyn WeakLinkFrom<'link>>
+                    ));
+                }
+                value
+            })
+            .collect()
+    }
 }
 
 /// Internal state of a [`WeakLink`]
 struct WeakLinkInner<'link, T> {
     /// [`HashMap`] of the link between this [`WeakLink`] and a target and its associated value
     targets: RefCell<HashMap<HashWeakPtr<TargetInner<'link>>, T>>,
+    /// Calls made while `targets` was already borrowed, see [`WeakLink::with_or_else_deferred`]
+    deferred: RefCell<VecDeque<DeferredFn<'link, T>>>,
 }
 
+type DeferredFn<'link, T> = Box<dyn FnOnce(&WeakLink<'link, T>) + 'link>;
+
 /// Trait to allow for a [`Target`] to contain references to [`WeakLink`]s it is associated to
 /// without needing to be generic over its associated value type
 trait WeakLinkFrom<'link> {
@@ -184,12 +264,24 @@ impl Target<'_> {
     /// Creates a new [`Target`]
     #[inline]
     pub(crate) fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a new [`Target`] with room for `capacity` links without reallocating
+    #[inline]
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
         Self {
             inner: Rc::new(TargetInner {
-                links: RefCell::new(HashSet::new()),
+                links: RefCell::new(HashSet::with_capacity(capacity)),
             }),
         }
     }
+
+    /// Number of links this [`Target`] has room for without reallocating
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> usize {
+        self.inner.links.borrow().capacity()
+    }
 }
 
 /// Internal state of a [`Target`]
@@ -207,3 +299,23 @@ impl Drop for Target<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Target, WeakLink};
+
+    #[test]
+    fn drain() {
+        let link = WeakLink::new();
+        let targets = [Target::new(), Target::new()];
+        for (value, target) in (1_i32..).zip(&targets) {
+            link.with_or_else(target, || value, |_| {});
+        }
+
+        let mut values = link.drain();
+        values.sort_unstable();
+        assert_eq!(values, [1_i32, 2_i32]);
+        assert!(targets.iter().all(|t| link.borrow(t).is_none()));
+        assert!(link.drain().is_empty());
+    }
+}